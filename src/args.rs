@@ -0,0 +1,218 @@
+use sim6502::{DynResult, Interrupt};
+
+/// options `compare` applies to both builds, it rejects the others
+const COMPARE_OPTIONS: &[&str] = &[
+    "--machine",
+    "--im-regs",
+    "--irq-at",
+    "--nmi-at",
+    "--max-cycles",
+];
+
+const USAGE: &str = "usage: sim6502 [options] [program.elf]
+       sim6502 disasm [options] program.elf [SYMBOL|ADDR [COUNT]]
+       sim6502 compare [options] baseline.elf candidate.elf
+
+commands:
+    disasm              disassemble code sections, or COUNT (default 16)
+                        instructions at SYMBOL or ADDR
+    compare             run two builds of a program and compare cycles, calls
+                        and code size per function; takes only --machine,
+                        --im-regs, --irq-at, --nmi-at and --max-cycles, and
+                        needs builds with symbols
+
+options:
+    --uds               listen on unix domain socket instead of tcp port 9001
+    --headless          run the program to completion without waiting for a debugger
+    --machine FILE      machine description (memory regions, ROM images, devices)
+    --symbols FILE      read symbols from FILE (separate debug info for a stripped binary)
+    --im-regs SPEC      imaginary register locations, e.g. `0x02:32` or `0x02:16,0x40:16`
+    --irq-at CYCLE      request IRQ at CYCLE (may be repeated)
+    --nmi-at CYCLE      request NMI at CYCLE (may be repeated)
+    --max-cycles N      stop a --headless run after N cycles (exit status 124)
+    --history N         instructions kept for the crash report (default 64, 0: off)
+    --profile           print cycles spent per function when the program ends
+    --callgrind FILE    write the function profile in callgrind format to FILE
+    --lcov FILE         write source line coverage in lcov format to FILE
+    --annotate FILE     write sources annotated with executions and cycles per
+                        line to FILE
+    --opcodes           print executions and cycles per opcode and addressing
+                        mode when the program ends
+    --opcodes-json FILE write them as JSON to FILE
+    --opcodes-by-function
+                        also break them down by function
+    --zp-stats          print reads and writes of imaginary registers and other
+                        zero page bytes per function when the program ends
+    --stack-usage       print the peak hardware and soft stack depth, and the
+                        call chains reaching them, when the program ends
+    --stack-check       stop on stack overflow / underflow, returns not going
+                        back to the caller and overwritten return addresses
+                        (SIGSEGV for the debugger, exit status 139 headless)
+    --profraw FILE      where to write llvm instrumentation counters of programs
+                        built with -fprofile-instr-generate (default:
+                        $LLVM_PROFILE_FILE, or default.profraw)
+    --sample-profile FILE
+                        sample the PC and write an llvm sample profile (AutoFDO
+                        text format) to FILE, for -fprofile-sample-use
+    --sample-period N   cycles between samples (default 100)
+    --timeline FILE     write function calls, device accesses and interrupts
+                        as a Chrome trace (JSON) to FILE, for Perfetto
+    --trace             log every executed instruction from the start
+                        (programs switch it with a write to the trace port,
+                        which the default machine lacks: map it with
+                        `trace = ADDR` in a --machine file)
+    --trace-file FILE   write the trace to FILE instead of stderr
+    --trace-filter SPEC only trace given functions and address ranges,
+                        e.g. `main,0x1000-0x10ff`
+";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum Command {
+    /// run program, under debugger control unless `--headless`
+    #[default]
+    Run,
+    Disasm {
+        location: Option<String>,
+        count: usize,
+    },
+    /// run `elf` and `candidate` headless and compare their profiles
+    Compare { candidate: String },
+}
+
+/// Command line options
+#[derive(Debug, Default)]
+pub struct Args {
+    pub command: Command,
+    pub uds: bool,
+    pub headless: bool,
+    pub elf: Option<String>,
+    pub machine: Option<String>,
+    pub symbols: Option<String>,
+    pub im_regs: Option<String>,
+    pub interrupts: Vec<(u64, Interrupt)>,
+    pub max_cycles: Option<u64>,
+    pub history: Option<usize>,
+    pub profile: bool,
+    pub callgrind: Option<String>,
+    pub lcov: Option<String>,
+    pub annotate: Option<String>,
+    pub opcodes: bool,
+    pub opcodes_json: Option<String>,
+    pub opcodes_by_function: bool,
+    pub zp_stats: bool,
+    pub stack_usage: bool,
+    pub stack_check: bool,
+    pub profraw: Option<String>,
+    pub sample_profile: Option<String>,
+    pub sample_period: Option<u64>,
+    pub timeline: Option<String>,
+    pub trace: bool,
+    pub trace_file: Option<String>,
+    pub trace_filter: Option<String>,
+}
+
+impl Args {
+    /// parses process arguments, exits with usage message on error
+    pub fn parse() -> Self {
+        match Self::try_parse() {
+            Ok(args) => args,
+            Err(err) => {
+                eprintln!("{}\n\n{}", err, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    fn try_parse() -> DynResult<Self> {
+        let mut args = Args::default();
+        let mut iter = std::env::args().skip(1).peekable();
+        match iter.peek().map(String::as_str) {
+            Some("disasm") => {
+                iter.next();
+                args.command = Command::Disasm {
+                    location: None,
+                    count: 16,
+                };
+            }
+            Some("compare") => {
+                iter.next();
+                args.command = Command::Compare {
+                    candidate: String::new(),
+                };
+            }
+            _ => {}
+        }
+        let mut positional = vec![];
+        let mut options = vec![];
+        while let Some(arg) = iter.next() {
+            if arg.starts_with('-') {
+                options.push(arg.clone());
+            }
+            let mut value = || iter.next().ok_or(format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--uds" => args.uds = true,
+                "--headless" => args.headless = true,
+                "--machine" => args.machine = Some(value()?),
+                "--symbols" => args.symbols = Some(value()?),
+                "--im-regs" => args.im_regs = Some(value()?),
+                "--irq-at" => args.interrupts.push((value()?.parse()?, Interrupt::Irq)),
+                "--nmi-at" => args.interrupts.push((value()?.parse()?, Interrupt::Nmi)),
+                "--max-cycles" => args.max_cycles = Some(value()?.parse()?),
+                "--history" => args.history = Some(value()?.parse()?),
+                "--profile" => args.profile = true,
+                "--callgrind" => args.callgrind = Some(value()?),
+                "--lcov" => args.lcov = Some(value()?),
+                "--annotate" => args.annotate = Some(value()?),
+                "--opcodes" => args.opcodes = true,
+                "--opcodes-json" => args.opcodes_json = Some(value()?),
+                "--opcodes-by-function" => args.opcodes_by_function = true,
+                "--zp-stats" => args.zp_stats = true,
+                "--stack-usage" => args.stack_usage = true,
+                "--stack-check" => args.stack_check = true,
+                "--profraw" => args.profraw = Some(value()?),
+                "--sample-profile" => args.sample_profile = Some(value()?),
+                "--sample-period" => args.sample_period = Some(value()?.parse()?),
+                "--timeline" => args.timeline = Some(value()?),
+                "--trace" => args.trace = true,
+                "--trace-file" => args.trace_file = Some(value()?),
+                "--trace-filter" => args.trace_filter = Some(value()?),
+                "-h" | "--help" => {
+                    eprint!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg).into()),
+                _ => positional.push(arg),
+            }
+        }
+        let mut positional = positional.into_iter();
+        args.elf = positional.next();
+        if let Command::Disasm { location, count } = &mut args.command {
+            if args.elf.is_none() {
+                return Err("disasm requires a program".into());
+            }
+            *location = positional.next();
+            if let Some(n) = positional.next() {
+                *count = n.parse()?;
+            }
+        }
+        if let Command::Compare { candidate } = &mut args.command {
+            match positional.next() {
+                Some(path) if args.elf.is_some() => *candidate = path,
+                _ => return Err("compare requires two programs".into()),
+            }
+            if let Some(opt) = options
+                .iter()
+                .find(|opt| !COMPARE_OPTIONS.contains(&opt.as_str()))
+            {
+                return Err(format!("{} is not supported by compare", opt).into());
+            }
+        }
+        if let Some(arg) = positional.next() {
+            return Err(format!("unexpected argument {}", arg).into());
+        }
+        if args.headless && args.elf.is_none() {
+            return Err("--headless requires a program".into());
+        }
+        Ok(args)
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::ops::Range;
+
+use gimli::{AttributeValue, DebuggingInformationEntry, EndianSlice, LittleEndian, Unit};
+use goblin::elf::Elf;
+
+use crate::lines::{load_dwarf, Dwarf};
+use crate::DynResult;
+
+type Entry<'a, 'u> = DebuggingInformationEntry<'a, 'u, EndianSlice<'a, LittleEndian>>;
+
+/// Default cycles between samples
+pub const DEFAULT_SAMPLE_PERIOD: u64 = 100;
+
+/// Samples the PC every `period` cycles.
+#[derive(Debug)]
+pub struct Sampler {
+    /// 0 when not sampling
+    period: u64,
+    next: u64,
+    /// samples per instruction address
+    samples: Vec<u64>,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self {
+            period: 0,
+            next: 0,
+            samples: vec![0; 0x10000],
+        }
+    }
+}
+
+impl Sampler {
+    pub fn enabled(&self) -> bool {
+        self.period > 0
+    }
+
+    /// Samples every `period` cycles from `cycle` on, 0 stops sampling.
+    pub fn set_period(&mut self, period: u64, cycle: u64) {
+        self.period = period;
+        self.next = cycle + period;
+    }
+
+    pub fn reset(&mut self, cycle: u64) {
+        self.samples.fill(0);
+        self.next = cycle + self.period;
+    }
+
+    /// Called every cycle with the address of the instruction running.
+    pub fn tick(&mut self, cycle: u64, pc: u16) {
+        while self.period > 0 && cycle >= self.next {
+            self.samples[pc as usize] += 1;
+            self.next += self.period;
+        }
+    }
+
+    pub fn samples(&self) -> &[u64] {
+        &self.samples
+    }
+}
+
+/// Function or inlined function instance with code.
+#[derive(Debug)]
+struct Scope {
+    ranges: Vec<Range<u64>>,
+    name: String,
+    decl_line: u32,
+    /// call site (line, discriminator) in the parent, for inlined functions
+    call_site: (u32, u32),
+    parent: Option<usize>,
+    level: usize,
+}
+
+/// Samples of a function, the way the sample profile text format nests
+/// them: body samples and inlined callees by (line offset, discriminator).
+#[derive(Debug, Default)]
+struct FuncProfile {
+    total: u64,
+    head: u64,
+    body: BTreeMap<(u32, u32), u64>,
+    inlined: BTreeMap<(u32, u32, String), FuncProfile>,
+}
+
+/// Base discriminator from a DWARF discriminator, as
+/// `DILocation::getBaseDiscriminator` decodes it.
+fn base_discriminator(disc: u64) -> u32 {
+    let disc = disc as u32;
+    if disc & 1 != 0 {
+        return 0;
+    }
+    let disc = disc >> 1;
+    if disc & 0x20 != 0 {
+        ((disc >> 1) & 0xfe0) | (disc & 0x1f)
+    } else {
+        disc & 0x1f
+    }
+}
+
+/// Name (linkage name preferred) and declaration line of a function DIE,
+/// following abstract origins and specifications.
+fn func_info(
+    dwarf: &Dwarf<'_>,
+    unit: &Unit<EndianSlice<'_, LittleEndian>>,
+    entry: &Entry<'_, '_>,
+) -> DynResult<(Option<String>, u32)> {
+    let mut linkage_name = None;
+    let mut name = None;
+    let mut decl_line = None;
+    let mut entry = entry.clone();
+    // abstract origin -> specification is as deep as it gets
+    for _ in 0..4 {
+        let attr_string = |at| -> DynResult<Option<String>> {
+            Ok(match entry.attr_value(at)? {
+                Some(value) => Some(
+                    dwarf
+                        .attr_string(unit, value)?
+                        .to_string_lossy()
+                        .into_owned(),
+                ),
+                None => None,
+            })
+        };
+        if linkage_name.is_none() {
+            linkage_name = attr_string(gimli::DW_AT_linkage_name)?;
+        }
+        if linkage_name.is_none() {
+            linkage_name = attr_string(gimli::DW_AT_MIPS_linkage_name)?;
+        }
+        if name.is_none() {
+            name = attr_string(gimli::DW_AT_name)?;
+        }
+        if decl_line.is_none() {
+            decl_line = entry
+                .attr_value(gimli::DW_AT_decl_line)?
+                .and_then(|v| v.udata_value());
+        }
+        let origin = match entry.attr_value(gimli::DW_AT_abstract_origin)? {
+            Some(origin) => Some(origin),
+            None => entry.attr_value(gimli::DW_AT_specification)?,
+        };
+        match origin {
+            Some(AttributeValue::UnitRef(offset)) => entry = unit.entry(offset)?,
+            _ => break,
+        }
+    }
+    Ok((linkage_name.or(name), decl_line.unwrap_or(0) as u32))
+}
+
+/// Functions and inlined function instances of all units, with their
+/// address ranges.
+fn scopes(dwarf: &Dwarf<'_>) -> DynResult<Vec<Scope>> {
+    let mut scopes: Vec<Scope> = vec![];
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        // (depth, index into `scopes`) of the enclosing scopes
+        let mut stack: Vec<(isize, usize)> = vec![];
+        let mut depth = 0;
+        let mut entries = unit.entries();
+        while let Some((delta, entry)) = entries.next_dfs()? {
+            depth += delta;
+            while stack.last().is_some_and(|(d, _)| *d >= depth) {
+                stack.pop();
+            }
+            let inlined = match entry.tag() {
+                gimli::DW_TAG_subprogram => false,
+                gimli::DW_TAG_inlined_subroutine => true,
+                _ => continue,
+            };
+            let mut ranges = vec![];
+            let mut iter = dwarf.die_ranges(&unit, entry)?;
+            while let Some(range) = iter.next()? {
+                ranges.push(range.begin..range.end);
+            }
+            // declarations and abstract instances have no code
+            if ranges.is_empty() {
+                continue;
+            }
+            let (name, decl_line) = func_info(dwarf, &unit, entry)?;
+            let call_site = if inlined {
+                let line = entry
+                    .attr_value(gimli::DW_AT_call_line)?
+                    .and_then(|v| v.udata_value());
+                let disc = entry
+                    .attr_value(gimli::DW_AT_GNU_discriminator)?
+                    .and_then(|v| v.udata_value());
+                (line.unwrap_or(0) as u32, disc.map_or(0, base_discriminator))
+            } else {
+                (0, 0)
+            };
+            let parent = stack.last().map(|(_, idx)| *idx).filter(|_| inlined);
+            scopes.push(Scope {
+                ranges,
+                name: name.unwrap_or_else(|| "<unknown>".into()),
+                decl_line,
+                call_site,
+                parent,
+                level: parent.map_or(0, |p| scopes[p].level + 1),
+            });
+            stack.push((depth, scopes.len() - 1));
+        }
+    }
+    Ok(scopes)
+}
+
+/// (line, base discriminator) of every address, from the line program.
+fn line_info(dwarf: &Dwarf<'_>) -> DynResult<Vec<Option<(u32, u32)>>> {
+    let mut lines = vec![None; 0x10000];
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let program = match unit.line_program.clone() {
+            Some(program) => program,
+            None => continue,
+        };
+        let mut range_start = None;
+        let mut rows = program.rows();
+        while let Some((_, row)) = rows.next_row()? {
+            if let Some((start, info)) = range_start.take() {
+                for addr in start..row.address().min(0x10000) {
+                    lines[addr as usize] = info;
+                }
+            }
+            if !row.end_sequence() {
+                let info = row
+                    .line()
+                    .map(|line| (line.get() as u32, base_discriminator(row.discriminator())));
+                range_start = Some((row.address(), info));
+            }
+        }
+    }
+    Ok(lines)
+}
+
+fn offset_key(line: u32, decl_line: u32, disc: u32) -> (u32, u32) {
+    (line.wrapping_sub(decl_line) & 0xffff, disc)
+}
+
+/// Writes `samples` (per instruction address) in llvm's sample profile text
+/// format, using debug info from `elf`.
+pub fn write_sample_profile(
+    elf: &Elf<'_>,
+    data: &[u8],
+    samples: &[u64],
+    out: &mut dyn Write,
+) -> DynResult<()> {
+    let dwarf = load_dwarf(elf, data)?;
+    let profiles = func_profiles(&scopes(&dwarf)?, &line_info(&dwarf)?, samples);
+    write_profiles(&profiles, out)
+}
+
+/// Samples per function, from the address ranges of `scopes` and the line
+/// of every address.
+fn func_profiles(
+    scopes: &[Scope],
+    lines: &[Option<(u32, u32)>],
+    samples: &[u64],
+) -> BTreeMap<String, FuncProfile> {
+    let mut profiles: BTreeMap<String, FuncProfile> = BTreeMap::new();
+    for (addr, &count) in samples.iter().enumerate() {
+        let (line, disc) = match lines[addr] {
+            Some(info) if count > 0 => info,
+            _ => continue,
+        };
+        let addr = addr as u64;
+        let innermost = scopes
+            .iter()
+            .enumerate()
+            .filter(|(_, scope)| scope.ranges.iter().any(|r| r.contains(&addr)))
+            .max_by_key(|(_, scope)| scope.level);
+        let mut idx = match innermost {
+            Some((idx, _)) => idx,
+            None => continue,
+        };
+        let mut chain = vec![idx];
+        while let Some(parent) = scopes[idx].parent {
+            chain.push(parent);
+            idx = parent;
+        }
+        chain.reverse();
+
+        let root = &scopes[chain[0]];
+        let mut profile = profiles.entry(root.name.clone()).or_default();
+        if root.ranges.first().is_some_and(|r| r.start == addr) {
+            profile.head += count;
+        }
+        profile.total += count;
+        for pair in chain.windows(2) {
+            let (outer, inner) = (&scopes[pair[0]], &scopes[pair[1]]);
+            let (line, disc) = offset_key(inner.call_site.0, outer.decl_line, inner.call_site.1);
+            profile = profile
+                .inlined
+                .entry((line, disc, inner.name.clone()))
+                .or_default();
+            profile.total += count;
+        }
+        let decl_line = scopes[*chain.last().unwrap()].decl_line;
+        *profile
+            .body
+            .entry(offset_key(line, decl_line, disc))
+            .or_default() += count;
+    }
+    profiles
+}
+
+fn write_profiles(profiles: &BTreeMap<String, FuncProfile>, out: &mut dyn Write) -> DynResult<()> {
+    for (name, profile) in profiles {
+        writeln!(out, "{}:{}:{}", name, profile.total, profile.head)?;
+        write_body(profile, 1, out)?;
+    }
+    Ok(())
+}
+
+fn write_body(profile: &FuncProfile, indent: usize, out: &mut dyn Write) -> DynResult<()> {
+    let location = |(line, disc): (u32, u32)| match disc {
+        0 => format!("{}", line),
+        _ => format!("{}.{}", line, disc),
+    };
+    for (key, count) in &profile.body {
+        writeln!(
+            out,
+            "{:indent$}{}: {}",
+            "",
+            location(*key),
+            count,
+            indent = indent
+        )?;
+    }
+    for ((line, disc, name), inlined) in &profile.inlined {
+        writeln!(
+            out,
+            "{:indent$}{}: {}:{}",
+            "",
+            location((*line, *disc)),
+            name,
+            inlined.total,
+            indent = indent
+        )?;
+        write_body(inlined, indent + 1, out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_text() {
+        let scopes = [
+            Scope {
+                ranges: vec![0x1000..0x1010, 0x3000..0x3004],
+                name: "main".into(),
+                decl_line: 10,
+                call_site: (0, 0),
+                parent: None,
+                level: 0,
+            },
+            Scope {
+                ranges: vec![0x1008..0x100c, 0x3000..0x3002],
+                name: "add".into(),
+                decl_line: 3,
+                call_site: (14, 2),
+                parent: Some(0),
+                level: 1,
+            },
+        ];
+        let mut lines = vec![None; 0x10000];
+        lines[0x1000] = Some((10, 0));
+        lines[0x1002] = Some((12, 0));
+        lines[0x1004] = Some((12, 1));
+        lines[0x1008] = Some((4, 0));
+        lines[0x100a] = Some((5, 0));
+        // outside of any function
+        lines[0x2000] = Some((1, 0));
+        let mut samples = vec![0; 0x10000];
+        for (addr, count) in [(0x1000, 2), (0x1002, 3), (0x1004, 1), (0x1006, 7)] {
+            samples[addr] = count;
+        }
+        for (addr, count) in [(0x1008, 4), (0x100a, 5), (0x2000, 6)] {
+            samples[addr] = count;
+        }
+
+        let mut out = vec![];
+        write_profiles(&func_profiles(&scopes, &lines, &samples), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+main:15:2
+ 0: 2
+ 2: 3
+ 2.1: 1
+ 4.2: add:9
+  1: 4
+  2: 5
+"
+        );
+    }
+
+    #[test]
+    fn discriminators() {
+        assert_eq!(base_discriminator(0), 0);
+        assert_eq!(base_discriminator(1), 0);
+        assert_eq!(base_discriminator(2 << 1), 2);
+        assert_eq!(base_discriminator(0x1f << 1), 0x1f);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::emu::{Emu, Event};
+use crate::DynResult;
+
+/// Cycle budget of `Emu::call`.
+pub const CALL_CYCLE_LIMIT: u64 = 100_000_000;
+
+/// Where called functions return to. Never executed: the call is over when
+/// PC gets there with the stack pointer back at its level before the call.
+const RETURN_ADDR: u16 = 0x0000;
+
+/// Soft stack pointer `__rs0` is not an argument register.
+const FIRST_ARG_RC: usize = 2;
+const LAST_ARG_RC: usize = 15;
+
+/// Argument of `Emu::call`. Signed values are passed as their unsigned
+/// counterparts (`-1i16 as u16`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Arg {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    Ptr(u16),
+}
+
+/// Return value registers of a finished `Emu::call`, decode them according
+/// to the return type of the function.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Return {
+    /// A, X, `__rc2`..`__rc7`
+    bytes: [u8; 8],
+}
+
+impl Return {
+    pub fn u8(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    pub fn u16(&self) -> u16 {
+        u16::from_le_bytes([self.bytes[0], self.bytes[1]])
+    }
+
+    pub fn u32(&self) -> u32 {
+        u32::from_le_bytes(self.bytes[..4].try_into().unwrap())
+    }
+
+    pub fn u64(&self) -> u64 {
+        u64::from_le_bytes(self.bytes)
+    }
+
+    /// pointers are returned in `__rs1`
+    pub fn ptr(&self) -> u16 {
+        u16::from_le_bytes([self.bytes[2], self.bytes[3]])
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Loc {
+    A,
+    X,
+    Rc(usize),
+}
+
+/// Assigns argument bytes to registers following the llvm-mos C calling
+/// convention: pointers go to the lowest free `__rsN` pair, everything else
+/// is split into bytes that go to A, X and then the lowest free `__rcN`.
+fn assign_registers(args: &[Arg]) -> Result<Vec<(Loc, u8)>, String> {
+    let mut a_used = false;
+    let mut x_used = false;
+    let mut rc_used = [false; LAST_ARG_RC + 1];
+    let mut assigned = vec![];
+
+    for (n, arg) in args.iter().enumerate() {
+        if let Arg::Ptr(ptr) = arg {
+            let rc = (FIRST_ARG_RC..LAST_ARG_RC)
+                .step_by(2)
+                .find(|rc| !rc_used[*rc] && !rc_used[rc + 1])
+                .ok_or_else(|| format!("argument {} doesn't fit in registers", n))?;
+            rc_used[rc] = true;
+            rc_used[rc + 1] = true;
+            assigned.push((Loc::Rc(rc), *ptr as u8));
+            assigned.push((Loc::Rc(rc + 1), (*ptr >> 8) as u8));
+            continue;
+        }
+
+        let bytes = match arg {
+            Arg::U8(val) => val.to_le_bytes().to_vec(),
+            Arg::U16(val) => val.to_le_bytes().to_vec(),
+            Arg::U32(val) => val.to_le_bytes().to_vec(),
+            Arg::U64(val) => val.to_le_bytes().to_vec(),
+            Arg::Ptr(_) => unreachable!(),
+        };
+        let free_rcs = (FIRST_ARG_RC..=LAST_ARG_RC).filter(|rc| !rc_used[*rc]);
+        let free = !a_used as usize + !x_used as usize + free_rcs.clone().count();
+        if free < bytes.len() {
+            // would be passed on the soft stack
+            return Err(format!("argument {} doesn't fit in registers", n));
+        }
+        let mut free_rcs = free_rcs.collect::<Vec<_>>().into_iter();
+        for b in bytes {
+            let loc = if !a_used {
+                a_used = true;
+                Loc::A
+            } else if !x_used {
+                x_used = true;
+                Loc::X
+            } else {
+                let rc = free_rcs.next().unwrap();
+                rc_used[rc] = true;
+                Loc::Rc(rc)
+            };
+            assigned.push((loc, b));
+        }
+    }
+    Ok(assigned)
+}
+
+impl Emu {
+    /// Calls function `name` of the loaded program with `args` and runs it
+    /// until it returns, as if it was called with `JSR` from the current
+    /// program state. See `call_with_limit`.
+    pub fn call(&mut self, name: &str, args: &[Arg]) -> DynResult<Return> {
+        self.call_with_limit(name, args, CALL_CYCLE_LIMIT)
+    }
+
+    /// Calls function `name` with `args`, giving up after `max_cycles`.
+    ///
+    /// Arguments are passed in registers only; calls needing the soft stack
+    /// for arguments or a struct return are not supported. The soft stack
+    /// pointer is set up from `__stack` if the program hasn't done it yet.
+    /// PC and S are restored afterwards, also when the call fails;
+    /// breakpoints are ignored during the call.
+    pub fn call_with_limit(
+        &mut self,
+        name: &str,
+        args: &[Arg],
+        max_cycles: u64,
+    ) -> DynResult<Return> {
+        let entry = self
+            .symbols
+            .addr(name)
+            .ok_or_else(|| format!("no symbol {}", name))?;
+        let rc_addr = |rc: usize| {
+            self.im_reg_map
+                .get(rc)
+                .ok_or_else(|| format!("location of __rc{} unknown", rc))
+        };
+
+        for (loc, val) in assign_registers(args)? {
+            match loc {
+                Loc::A => self.cpu.set_accumulator(val),
+                Loc::X => self.cpu.set_x_register(val),
+                Loc::Rc(rc) => {
+                    let addr = rc_addr(rc)?;
+                    self.system.poke(addr, val)
+                }
+            }
+        }
+
+        let (sp_lo, sp_hi) = (rc_addr(0)?, rc_addr(1)?);
+        if self.system.peek(sp_lo) == 0 && self.system.peek(sp_hi) == 0 {
+            if let Some(stack) = self.symbols.addr("__stack") {
+                self.system.poke(sp_lo, stack as u8);
+                self.system.poke(sp_hi, (stack >> 8) as u8);
+            }
+        }
+
+        // what JSR would push: return address - 1
+        let saved_pc = self.cpu.get_program_counter();
+        let s = self.cpu.get_stack_pointer();
+        let ret = RETURN_ADDR.wrapping_sub(1);
+        self.system.poke(0x100 | s as u16, (ret >> 8) as u8);
+        self.system
+            .poke(0x100 | s.wrapping_sub(1) as u16, ret as u8);
+        self.cpu.set_stack_pointer(s.wrapping_sub(2));
+        self.cpu.set_program_counter(entry);
+        self.enter_function(entry, s, self.cycles());
+        if self.stack_check.enabled() {
+            let system = &self.system;
+            self.stack_check
+                .call(s.wrapping_sub(2), |addr| system.peek(addr));
+        }
+
+        let finished = self.finish_call(name, s, max_cycles);
+        self.cpu.set_program_counter(saved_pc);
+        self.cpu.set_stack_pointer(s);
+        // the call's frame and whatever it left behind when it failed
+        self.leave_functions(s, self.cycles());
+        finished?;
+
+        let mut result = Return::default();
+        result.bytes[0] = self.cpu.get_accumulator();
+        result.bytes[1] = self.cpu.get_x_register();
+        for (i, b) in result.bytes[2..].iter_mut().enumerate() {
+            if let Some(addr) = self.im_reg_map.get(FIRST_ARG_RC + i) {
+                *b = self.system.peek(addr);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Runs the called function until it returns to `RETURN_ADDR` with the
+    /// stack pointer back at `s`.
+    fn finish_call(&mut self, name: &str, s: u8, max_cycles: u64) -> DynResult<()> {
+        let limit = self.cycles().saturating_add(max_cycles);
+        loop {
+            match self.step() {
+                Some(Event::Halted) => {
+                    return Err(format!(
+                        "program exited with status {} during call to {}",
+                        self.exit_code().unwrap_or_default(),
+                        name
+                    )
+                    .into())
+                }
+                Some(Event::StackFault(fault)) => {
+                    return Err(format!("{} during call to {}", fault, name).into())
+                }
+                _ => {}
+            }
+            if self.cpu.get_remaining_cycles() == 0
+                && self.cpu.get_program_counter() == RETURN_ADDR
+                && self.cpu.get_stack_pointer() == s
+            {
+                return Ok(());
+            }
+            if self.cycles() >= limit {
+                return Err(format!("{} didn't return within {} cycles", name, max_cycles).into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pointers_and_bytes() {
+        let args = [
+            Arg::Ptr(0x1234),
+            Arg::U8(1),
+            Arg::U16(0x0302),
+            Arg::Ptr(0x5678),
+        ];
+        assert_eq!(
+            assign_registers(&args).unwrap(),
+            [
+                (Loc::Rc(2), 0x34),
+                (Loc::Rc(3), 0x12),
+                (Loc::A, 1),
+                (Loc::X, 2),
+                (Loc::Rc(4), 3),
+                (Loc::Rc(6), 0x78),
+                (Loc::Rc(7), 0x56),
+            ]
+        );
+    }
+
+    #[test]
+    fn pointers_use_rs1_to_rs7() {
+        let args = [Arg::Ptr(0xabcd); 7];
+        let assigned = assign_registers(&args).unwrap();
+        let rcs: Vec<_> = assigned.iter().map(|(loc, _)| *loc).collect();
+        assert_eq!(rcs, (2..=15).map(Loc::Rc).collect::<Vec<_>>());
+        assert!(assign_registers(&[Arg::Ptr(0); 8]).is_err());
+
+        // bytes still fit in A and X
+        let mut args = args.to_vec();
+        args.push(Arg::U16(0x0100));
+        assert_eq!(
+            assign_registers(&args).unwrap()[14..],
+            [(Loc::A, 0x00), (Loc::X, 0x01)]
+        );
+        args.push(Arg::U8(0));
+        assert!(assign_registers(&args).is_err());
+    }
+
+    #[test]
+    fn bytes_use_a_x_then_rc2_to_rc15() {
+        let args = [Arg::U64(0x0807060504030201), Arg::U64(0x100f0e0d0c0b0a09)];
+        let assigned = assign_registers(&args).unwrap();
+        let expected: Vec<_> = [Loc::A, Loc::X]
+            .into_iter()
+            .chain((2..=15).map(Loc::Rc))
+            .zip(1..=16)
+            .collect();
+        assert_eq!(assigned, expected);
+
+        assert!(assign_registers(&[Arg::U64(0), Arg::U64(0), Arg::U8(0)]).is_err());
+        // arguments aren't split between registers and the soft stack
+        assert!(assign_registers(&[Arg::U64(0), Arg::U32(0), Arg::U64(0)]).is_err());
+    }
+}
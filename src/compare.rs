@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+
+use crate::emu::Emu;
+
+/// Profile and code size of one function
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub name: String,
+    /// code size in bytes, from the symbol table
+    pub size: u16,
+    pub calls: u64,
+    pub exclusive: u64,
+    pub inclusive: u64,
+}
+
+/// Outcome of one run, for comparing builds of a program.
+#[derive(Debug, Default, Clone)]
+pub struct RunProfile {
+    pub cycles: u64,
+    pub exit_code: Option<u8>,
+    pub output: Vec<u8>,
+    /// every function symbol of the program, run or not
+    pub functions: Vec<FunctionProfile>,
+}
+
+impl Emu {
+    /// Cycles, output and per function profile of the run so far. Needs the
+    /// profiler to have been on from the start (`set_profiling`).
+    pub fn run_profile(&self) -> RunProfile {
+        let stats = self.profiler.function_stats();
+        let functions = self
+            .symbols
+            .functions()
+            .map(|sym| {
+                let stats = stats.get(&sym.addr).cloned().unwrap_or_default();
+                FunctionProfile {
+                    name: sym.name.clone(),
+                    size: sym.size,
+                    calls: stats.calls,
+                    exclusive: stats.exclusive,
+                    inclusive: stats.inclusive,
+                }
+            })
+            .collect();
+        RunProfile {
+            cycles: self.cycles(),
+            exit_code: self.exit_code(),
+            output: self.output(),
+            functions,
+        }
+    }
+}
+
+impl RunProfile {
+    /// Table of the functions whose cycles, calls or size differ between
+    /// this run and `cand`, matched by name, biggest change in exclusive
+    /// cycles first. Rows where the candidate got slower or bigger are
+    /// marked with `!`.
+    pub fn compare(&self, cand: &RunProfile) -> String {
+        let base = self;
+        let mut funcs: BTreeMap<&str, (Option<&FunctionProfile>, Option<&FunctionProfile>)> =
+            BTreeMap::new();
+        for func in &base.functions {
+            funcs.entry(&func.name).or_default().0 = Some(func);
+        }
+        for func in &cand.functions {
+            funcs.entry(&func.name).or_default().1 = Some(func);
+        }
+
+        let mut report = String::new();
+        if base.exit_code != cand.exit_code {
+            report += &format!(
+                "warning: exit status differs: {} vs {}\n",
+                status(base.exit_code),
+                status(cand.exit_code)
+            );
+        }
+        if base.output != cand.output {
+            report += "warning: console output differs\n";
+        }
+        report += &format!(
+            "total cycles: {} -> {}, {}\n",
+            base.cycles,
+            cand.cycles,
+            change(base.cycles, cand.cycles)
+        );
+        let size = |funcs: &[FunctionProfile]| funcs.iter().map(|f| f.size as u64).sum::<u64>();
+        let (base_size, cand_size) = (size(&base.functions), size(&cand.functions));
+        report += &format!(
+            "total code size: {} -> {}, {}\n\n",
+            base_size,
+            cand_size,
+            change(base_size, cand_size)
+        );
+
+        let empty = FunctionProfile::default();
+        let mut changed: Vec<_> = funcs
+            .into_iter()
+            .map(|(name, (b, c))| {
+                (
+                    name,
+                    b.is_some(),
+                    c.is_some(),
+                    b.unwrap_or(&empty),
+                    c.unwrap_or(&empty),
+                )
+            })
+            .filter(|(_, in_b, in_c, b, c)| {
+                !(*in_b && *in_c)
+                    || (b.exclusive, b.inclusive, b.calls, b.size)
+                        != (c.exclusive, c.inclusive, c.calls, c.size)
+            })
+            .collect();
+        changed.sort_by_key(|(name, _, _, b, c)| {
+            (std::cmp::Reverse(b.exclusive.abs_diff(c.exclusive)), *name)
+        });
+
+        report += &format!(
+            "  {:>23} {:>17} {:>23} {:>15} {:>13}  function\n",
+            "exclusive cycles", "change", "inclusive cycles", "calls", "size"
+        );
+        for (name, in_base, in_cand, b, c) in changed {
+            let regression =
+                c.exclusive > b.exclusive || c.inclusive > b.inclusive || c.size > b.size;
+            let note = match (in_base, in_cand) {
+                (false, _) => " (new)",
+                (_, false) => " (removed)",
+                _ => "",
+            };
+            report += &format!(
+                "{} {:>23} {:>17} {:>23} {:>15} {:>13}  {}{}\n",
+                if regression { '!' } else { ' ' },
+                format!("{} -> {}", b.exclusive, c.exclusive),
+                change(b.exclusive, c.exclusive),
+                format!("{} -> {}", b.inclusive, c.inclusive),
+                format!("{} -> {}", b.calls, c.calls),
+                format!("{} -> {}", b.size, c.size),
+                name,
+                note
+            );
+        }
+        report
+    }
+}
+
+/// `+N (+P%)` from `base` to `cand`
+fn change(base: u64, cand: u64) -> String {
+    let diff = cand as i64 - base as i64;
+    if base == 0 {
+        return format!("{:+}", diff);
+    }
+    format!("{:+} ({:+.1}%)", diff, diff as f64 * 100.0 / base as f64)
+}
+
+fn status(exit_code: Option<u8>) -> String {
+    exit_code.map_or("none".into(), |code| code.to_string())
+}
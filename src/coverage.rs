@@ -0,0 +1,141 @@
+use std::io::Write;
+
+use crate::lines::LineTable;
+
+/// Execution count and cycles of one source line
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct LineStats {
+    /// times the line was executed: the highest count of its instructions
+    pub count: u64,
+    /// cycles spent in all instructions of the line
+    pub cycles: u64,
+}
+
+/// Execution counts and cycles per instruction address, mapped to source
+/// lines through a `LineTable` when reporting.
+#[derive(Debug)]
+pub struct Coverage {
+    enabled: bool,
+    counts: Vec<u64>,
+    cycles: Vec<u64>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            counts: vec![0; 0x10000],
+            cycles: vec![0; 0x10000],
+        }
+    }
+}
+
+impl Coverage {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.fill(0);
+        self.cycles.fill(0);
+    }
+
+    /// Instruction at `pc` ran, taking `cycles`.
+    pub fn instruction(&mut self, pc: u16, cycles: u64) {
+        self.counts[pc as usize] += 1;
+        self.cycles[pc as usize] += cycles;
+    }
+
+    /// Stats for each of `lines.locs`.
+    pub fn line_stats(&self, lines: &LineTable) -> Vec<LineStats> {
+        let mut stats = vec![LineStats::default(); lines.locs.len()];
+        for addr in 0..=0xffff {
+            if let Some(id) = lines.loc_id(addr) {
+                let line = &mut stats[id];
+                line.count = line.count.max(self.counts[addr as usize]);
+                line.cycles += self.cycles[addr as usize];
+            }
+        }
+        stats
+    }
+
+    /// (line, stats) of every line with code in file `file`, by line number
+    fn file_lines(
+        &self,
+        lines: &LineTable,
+        stats: &[LineStats],
+        file: usize,
+    ) -> Vec<(u32, LineStats)> {
+        let mut file_lines: Vec<_> = lines
+            .locs
+            .iter()
+            .zip(stats)
+            .filter(|((f, _), _)| *f as usize == file)
+            .map(|((_, line), stats)| (*line, *stats))
+            .collect();
+        file_lines.sort_by_key(|(line, _)| *line);
+        file_lines
+    }
+
+    /// Writes line coverage in lcov tracefile format.
+    pub fn write_lcov(&self, lines: &LineTable, out: &mut dyn Write) -> std::io::Result<()> {
+        let stats = self.line_stats(lines);
+        writeln!(out, "TN:")?;
+        for (file, path) in lines.files.iter().enumerate() {
+            let file_lines = self.file_lines(lines, &stats, file);
+            writeln!(out, "SF:{}", path)?;
+            for (line, stats) in &file_lines {
+                writeln!(out, "DA:{},{}", line, stats.count)?;
+            }
+            writeln!(out, "LF:{}", file_lines.len())?;
+            let hit = file_lines
+                .iter()
+                .filter(|(_, stats)| stats.count > 0)
+                .count();
+            writeln!(out, "LH:{}", hit)?;
+            writeln!(out, "end_of_record")?;
+        }
+        Ok(())
+    }
+
+    /// Writes source files annotated with execution count and cycles per
+    /// line. Files that can't be read are listed by line number only.
+    pub fn write_annotated(&self, lines: &LineTable, out: &mut dyn Write) -> std::io::Result<()> {
+        let stats = self.line_stats(lines);
+        for (file, path) in lines.files.iter().enumerate() {
+            let file_lines = self.file_lines(lines, &stats, file);
+            writeln!(out, "==== {}", path)?;
+            writeln!(out, "{:>10} {:>12}", "count", "cycles")?;
+            match std::fs::read_to_string(path) {
+                Ok(source) => {
+                    let mut file_lines = file_lines.iter().peekable();
+                    for (n, text) in source.lines().enumerate() {
+                        let n = n as u32 + 1;
+                        while file_lines.peek().is_some_and(|(line, _)| *line < n) {
+                            file_lines.next();
+                        }
+                        match file_lines.peek() {
+                            Some((line, stats)) if *line == n => writeln!(
+                                out,
+                                "{:>10} {:>12}  {:>5}: {}",
+                                stats.count, stats.cycles, n, text
+                            )?,
+                            _ => writeln!(out, "{:>10} {:>12}  {:>5}: {}", "", "", n, text)?,
+                        }
+                    }
+                }
+                Err(_) => {
+                    for (line, stats) in &file_lines {
+                        writeln!(out, "{:>10} {:>12}  {:>5}", stats.count, stats.cycles, line)?;
+                    }
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Memory mapped peripheral attached to the `System` bus.
+///
+/// All port numbers are offsets from the base address the device is mapped
+/// at. Devices are clocked with the number of elapsed CPU cycles and drive
+/// the CPU interrupt inputs through `irq` / `nmi`, which are sampled after
+/// every bus access and tick.
+pub trait Device {
+    /// Number of consecutive ports occupied by the device.
+    fn size(&self) -> u16;
+
+    /// Short name, used in the timeline.
+    fn name(&self) -> &str {
+        "device"
+    }
+
+    /// CPU read of port `offset`. Defaults to `peek` for devices where
+    /// reading has no side effects.
+    fn read(&mut self, offset: u16) -> u8 {
+        self.peek(offset)
+    }
+
+    /// CPU write of `data` to port `offset`.
+    fn write(&mut self, offset: u16, data: u8);
+
+    /// Debugger read of port `offset`: current register state, without any
+    /// side effects a CPU read would have.
+    fn peek(&self, offset: u16) -> u8;
+
+    /// Advances the device by `cycles` CPU cycles.
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// Level of the device IRQ output, active while `true`.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Level of the device NMI output; NMI is taken on the rising edge.
+    fn nmi(&self) -> bool {
+        false
+    }
+
+    /// Exit status, once the device requested the end of the simulation.
+    fn exit_status(&self) -> Option<u8> {
+        None
+    }
+
+    /// Brings the device back to power-on state when a program is loaded.
+    fn reset(&mut self) {}
+}
+
+/// Cycle counter: reading port 0 latches the counter, ports 0-3 return it
+/// (little endian).
+#[derive(Debug, Default)]
+pub struct Clock {
+    cycles: u64,
+    cycles_save: u64,
+}
+
+impl Device for Clock {
+    fn name(&self) -> &str {
+        "clock"
+    }
+
+    fn size(&self) -> u16 {
+        4
+    }
+
+    fn read(&mut self, offset: u16) -> u8 {
+        if offset == 0 {
+            self.cycles_save = self.cycles;
+        }
+        self.peek(offset)
+    }
+
+    fn write(&mut self, _offset: u16, _data: u8) {}
+
+    fn peek(&self, offset: u16) -> u8 {
+        match offset {
+            0 => (self.cycles & 0xff) as u8,
+            _ => ((self.cycles_save >> (offset * 8)) & 0xff) as u8,
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.cycles += cycles;
+    }
+
+    fn reset(&mut self) {
+        *self = Default::default();
+    }
+}
+
+/// Exit port: the written value is the exit status of the program.
+#[derive(Debug, Default)]
+pub struct Exit {
+    status: Option<u8>,
+}
+
+impl Device for Exit {
+    fn name(&self) -> &str {
+        "exit"
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn write(&mut self, _offset: u16, data: u8) {
+        self.status = Some(data);
+    }
+
+    fn peek(&self, _offset: u16) -> u8 {
+        0
+    }
+
+    fn exit_status(&self) -> Option<u8> {
+        self.status
+    }
+
+    fn reset(&mut self) {
+        self.status = None;
+    }
+}
+
+/// Text written to the console device.
+#[derive(Debug, Default)]
+pub struct ConsoleOutput {
+    pub data: Vec<u8>,
+    /// also print characters to stderr as they are written
+    pub echo: bool,
+}
+
+/// Character output, captured in a `ConsoleOutput` shared with the owner of
+/// the bus.
+#[derive(Debug, Default)]
+pub struct Console {
+    output: Rc<RefCell<ConsoleOutput>>,
+}
+
+impl Console {
+    pub fn new(output: Rc<RefCell<ConsoleOutput>>) -> Self {
+        Self { output }
+    }
+}
+
+impl Device for Console {
+    fn name(&self) -> &str {
+        "console"
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn write(&mut self, _offset: u16, data: u8) {
+        let mut output = self.output.borrow_mut();
+        let data = data & 0x7f;
+        if output.echo {
+            eprint!("{}", data as char);
+        }
+        output.data.push(data);
+    }
+
+    fn peek(&self, _offset: u16) -> u8 {
+        0
+    }
+}
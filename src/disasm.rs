@@ -0,0 +1,267 @@
+use crate::im_regs::ImRegMap;
+use crate::symbols::Symbols;
+
+/// Addressing modes of the NMOS 6502
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+impl Mode {
+    /// instruction length, including the opcode
+    pub fn size(self) -> u16 {
+        match self {
+            Mode::Implied | Mode::Accumulator => 1,
+            Mode::Absolute | Mode::AbsoluteX | Mode::AbsoluteY | Mode::Indirect => 3,
+            _ => 2,
+        }
+    }
+
+    /// operand syntax, e.g. `(zp),y`
+    pub fn syntax(self) -> &'static str {
+        match self {
+            Mode::Implied => "impl",
+            Mode::Accumulator => "a",
+            Mode::Immediate => "#imm",
+            Mode::ZeroPage => "zp",
+            Mode::ZeroPageX => "zp,x",
+            Mode::ZeroPageY => "zp,y",
+            Mode::Absolute => "abs",
+            Mode::AbsoluteX => "abs,x",
+            Mode::AbsoluteY => "abs,y",
+            Mode::Indirect => "(abs)",
+            Mode::IndirectX => "(zp,x)",
+            Mode::IndirectY => "(zp),y",
+            Mode::Relative => "rel",
+        }
+    }
+}
+
+/// Mnemonic and addressing mode of documented NMOS 6502 opcodes.
+pub fn opcode_info(opcode: u8) -> Option<(&'static str, Mode)> {
+    use Mode::*;
+    // the ALU group shares one layout of addressing modes
+    let alu = |name| {
+        Some((
+            name,
+            match opcode & 0x1f {
+                0x01 => IndirectX,
+                0x05 => ZeroPage,
+                0x09 => Immediate,
+                0x0d => Absolute,
+                0x11 => IndirectY,
+                0x15 => ZeroPageX,
+                0x19 => AbsoluteY,
+                _ => AbsoluteX,
+            },
+        ))
+    };
+    // shifts and rotates
+    let rmw = |name| {
+        Some((
+            name,
+            match opcode & 0x1f {
+                0x06 => ZeroPage,
+                0x0a => Accumulator,
+                0x0e => Absolute,
+                0x16 => ZeroPageX,
+                _ => AbsoluteX,
+            },
+        ))
+    };
+    match opcode {
+        0x01 | 0x05 | 0x09 | 0x0d | 0x11 | 0x15 | 0x19 | 0x1d => alu("ora"),
+        0x21 | 0x25 | 0x29 | 0x2d | 0x31 | 0x35 | 0x39 | 0x3d => alu("and"),
+        0x41 | 0x45 | 0x49 | 0x4d | 0x51 | 0x55 | 0x59 | 0x5d => alu("eor"),
+        0x61 | 0x65 | 0x69 | 0x6d | 0x71 | 0x75 | 0x79 | 0x7d => alu("adc"),
+        0x81 | 0x85 | 0x8d | 0x91 | 0x95 | 0x99 | 0x9d => alu("sta"),
+        0xa1 | 0xa5 | 0xa9 | 0xad | 0xb1 | 0xb5 | 0xb9 | 0xbd => alu("lda"),
+        0xc1 | 0xc5 | 0xc9 | 0xcd | 0xd1 | 0xd5 | 0xd9 | 0xdd => alu("cmp"),
+        0xe1 | 0xe5 | 0xe9 | 0xed | 0xf1 | 0xf5 | 0xf9 | 0xfd => alu("sbc"),
+
+        0x06 | 0x0a | 0x0e | 0x16 | 0x1e => rmw("asl"),
+        0x26 | 0x2a | 0x2e | 0x36 | 0x3e => rmw("rol"),
+        0x46 | 0x4a | 0x4e | 0x56 | 0x5e => rmw("lsr"),
+        0x66 | 0x6a | 0x6e | 0x76 | 0x7e => rmw("ror"),
+        0xc6 | 0xce | 0xd6 | 0xde => rmw("dec"),
+        0xe6 | 0xee | 0xf6 | 0xfe => rmw("inc"),
+
+        0x10 => Some(("bpl", Relative)),
+        0x30 => Some(("bmi", Relative)),
+        0x50 => Some(("bvc", Relative)),
+        0x70 => Some(("bvs", Relative)),
+        0x90 => Some(("bcc", Relative)),
+        0xb0 => Some(("bcs", Relative)),
+        0xd0 => Some(("bne", Relative)),
+        0xf0 => Some(("beq", Relative)),
+
+        0x24 => Some(("bit", ZeroPage)),
+        0x2c => Some(("bit", Absolute)),
+        0x4c => Some(("jmp", Absolute)),
+        0x6c => Some(("jmp", Indirect)),
+        0x20 => Some(("jsr", Absolute)),
+
+        0x86 => Some(("stx", ZeroPage)),
+        0x96 => Some(("stx", ZeroPageY)),
+        0x8e => Some(("stx", Absolute)),
+        0x84 => Some(("sty", ZeroPage)),
+        0x94 => Some(("sty", ZeroPageX)),
+        0x8c => Some(("sty", Absolute)),
+        0xa2 => Some(("ldx", Immediate)),
+        0xa6 => Some(("ldx", ZeroPage)),
+        0xb6 => Some(("ldx", ZeroPageY)),
+        0xae => Some(("ldx", Absolute)),
+        0xbe => Some(("ldx", AbsoluteY)),
+        0xa0 => Some(("ldy", Immediate)),
+        0xa4 => Some(("ldy", ZeroPage)),
+        0xb4 => Some(("ldy", ZeroPageX)),
+        0xac => Some(("ldy", Absolute)),
+        0xbc => Some(("ldy", AbsoluteX)),
+        0xe0 => Some(("cpx", Immediate)),
+        0xe4 => Some(("cpx", ZeroPage)),
+        0xec => Some(("cpx", Absolute)),
+        0xc0 => Some(("cpy", Immediate)),
+        0xc4 => Some(("cpy", ZeroPage)),
+        0xcc => Some(("cpy", Absolute)),
+
+        0x00 => Some(("brk", Implied)),
+        0x08 => Some(("php", Implied)),
+        0x18 => Some(("clc", Implied)),
+        0x28 => Some(("plp", Implied)),
+        0x38 => Some(("sec", Implied)),
+        0x40 => Some(("rti", Implied)),
+        0x48 => Some(("pha", Implied)),
+        0x58 => Some(("cli", Implied)),
+        0x60 => Some(("rts", Implied)),
+        0x68 => Some(("pla", Implied)),
+        0x78 => Some(("sei", Implied)),
+        0x88 => Some(("dey", Implied)),
+        0x8a => Some(("txa", Implied)),
+        0x98 => Some(("tya", Implied)),
+        0x9a => Some(("txs", Implied)),
+        0xa8 => Some(("tay", Implied)),
+        0xaa => Some(("tax", Implied)),
+        0xb8 => Some(("clv", Implied)),
+        0xba => Some(("tsx", Implied)),
+        0xc8 => Some(("iny", Implied)),
+        0xca => Some(("dex", Implied)),
+        0xd8 => Some(("cld", Implied)),
+        0xe8 => Some(("inx", Implied)),
+        0xea => Some(("nop", Implied)),
+        0xf8 => Some(("sed", Implied)),
+        _ => None,
+    }
+}
+
+/// Decoded instruction
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: [u8; 3],
+    /// `None` for undocumented opcodes, shown as `.byte`
+    pub op: Option<(&'static str, Mode)>,
+}
+
+impl Instruction {
+    /// Decodes instruction at `addr`, reading memory with `peek`.
+    pub fn decode(addr: u16, peek: impl Fn(u16) -> u8) -> Self {
+        Self {
+            addr,
+            bytes: [0, 1, 2].map(|i| peek(addr.wrapping_add(i))),
+            op: opcode_info(peek(addr)),
+        }
+    }
+
+    pub fn size(&self) -> u16 {
+        self.op.map_or(1, |(_, mode)| mode.size())
+    }
+
+    /// 8 or 16-bit operand, as it is encoded
+    pub fn operand(&self) -> u16 {
+        match self.size() {
+            3 => u16::from_le_bytes([self.bytes[1], self.bytes[2]]),
+            2 => self.bytes[1] as u16,
+            _ => 0,
+        }
+    }
+
+    /// Destination of branches, `JMP` and `JSR`.
+    pub fn target(&self) -> Option<u16> {
+        match self.op? {
+            (_, Mode::Relative) => Some(
+                self.addr
+                    .wrapping_add(2)
+                    .wrapping_add(self.bytes[1] as i8 as u16),
+            ),
+            ("jmp" | "jsr", Mode::Absolute) => Some(self.operand()),
+            _ => None,
+        }
+    }
+
+    /// Assembly text: jump targets as symbols, zero page operands as
+    /// imaginary register names where known.
+    pub(crate) fn text(&self, symbols: &Symbols, im_regs: &ImRegMap) -> String {
+        let (name, mode) = match self.op {
+            Some(op) => op,
+            None => return format!(".byte ${:02x}", self.bytes[0]),
+        };
+        let zp = || match im_regs.index_of(self.bytes[1] as u16) {
+            Some(idx) => format!("__rc{}", idx),
+            None => format!("${:02x}", self.bytes[1]),
+        };
+        let abs = || format!("${:04x}", self.operand());
+        let target = || {
+            let target = self.target().unwrap();
+            symbols
+                .symbolize(target)
+                .unwrap_or_else(|| format!("${:04x}", target))
+        };
+        let operand = match mode {
+            Mode::Implied => String::new(),
+            Mode::Accumulator => "a".into(),
+            Mode::Immediate => format!("#${:02x}", self.bytes[1]),
+            Mode::ZeroPage => zp(),
+            Mode::ZeroPageX => format!("{},x", zp()),
+            Mode::ZeroPageY => format!("{},y", zp()),
+            Mode::Absolute if self.target().is_some() => target(),
+            Mode::Absolute => abs(),
+            Mode::AbsoluteX => format!("{},x", abs()),
+            Mode::AbsoluteY => format!("{},y", abs()),
+            Mode::Indirect => format!("({})", abs()),
+            Mode::IndirectX => format!("({},x)", zp()),
+            Mode::IndirectY => format!("({}),y", zp()),
+            Mode::Relative => target(),
+        };
+        if operand.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{} {}", name, operand)
+        }
+    }
+
+    /// Listing line: address, raw bytes and assembly text.
+    pub(crate) fn line(&self, symbols: &Symbols, im_regs: &ImRegMap) -> String {
+        let bytes: Vec<String> = self.bytes[..self.size() as usize]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        format!(
+            "{:04x}:  {:<9} {}",
+            self.addr,
+            bytes.join(" "),
+            self.text(symbols, im_regs)
+        )
+    }
+}
@@ -1,8 +1,33 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::autofdo::Sampler;
+use crate::coverage::{Coverage, LineStats};
+use crate::device::{Clock, Console, ConsoleOutput, Device, Exit};
+use crate::disasm::{opcode_info, Instruction};
+use crate::history::History;
+use crate::im_regs::ImRegMap;
+use crate::interrupts::{Interrupt, InterruptLines};
+use crate::lines::LineTable;
+use crate::machine::Machine;
+use crate::memory_map::MemoryMap;
+use crate::opcode_stats::OpcodeStats;
+use crate::profile::Profiler;
+use crate::profraw::ProfSections;
+use crate::stack_check::{StackCheck, StackFault};
+use crate::stack_usage::StackUsage;
+use crate::symbols::Symbols;
+use crate::timeline::Timeline;
+use crate::timer::Timer;
+use crate::trace::{Access, Trace, TraceEntry};
+use crate::vfs::Vfs;
+use crate::zp_stats::ZeroPageStats;
 use crate::DynResult;
-use std::collections::HashMap;
 
 use emulator_6502::{Interface6502, MOS6502};
-use goblin::elf::sym::{st_bind, STB_GLOBAL};
+
+/// IRQ line shared by all devices on the bus
+const DEVICES_IRQ_LINE: u32 = 0;
 
 #[allow(dead_code)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -12,6 +37,64 @@ pub enum Event {
     Break,
     WatchWrite(u16),
     WatchRead(u16),
+    StackFault(StackFault),
+}
+
+/// Why `Emu::run_for` returned
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// program wrote its exit status to the exit port
+    Exited(u8),
+    /// hit a breakpoint at given address
+    Breakpoint(u16),
+    /// the cycle budget ran out
+    CycleLimit,
+    /// the stack check caught a stack overflow or a corrupted return address
+    StackFault(StackFault),
+}
+
+/// CPU registers
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Registers {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub s: u8,
+    pub p: u8,
+}
+
+/// Functions running, followed through `JSR`/`RTS`, `BRK`/interrupts/`RTI`
+/// and the stack pointer: a function is left once S is back at its level
+/// before the call. Calls and returns are passed on to the profilers.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct CallStack {
+    /// (entry address, stack pointer before the call), innermost last. 16
+    /// bit stack pointers, so the outermost frame never returns
+    frames: Vec<(u16, u16)>,
+}
+
+impl CallStack {
+    /// Starts over in function `pc`.
+    fn reset(&mut self, pc: u16) {
+        self.frames = vec![(pc, 0x100)];
+    }
+
+    /// Leaves the innermost frame if the stack was unwound past it.
+    fn pop_returned(&mut self, sp: u8) -> bool {
+        match self.frames.last() {
+            Some((_, return_sp)) if sp as u16 >= *return_sp => {
+                self.frames.pop();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Entry addresses of the functions running, outermost first.
+    fn funcs(&self) -> impl Iterator<Item = u16> + Clone + '_ {
+        self.frames.iter().map(|(func, _)| *func)
+    }
 }
 
 #[derive(Debug)]
@@ -22,62 +105,267 @@ pub enum ExecMode {
     RangeStep(u16, u16),
 }
 
-pub struct InMemoryFile {
-    pub filename: String,
-    pub data: Vec<u8>,
+/// What answers bus accesses at a given address
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Slot {
+    Ram,
+    Rom,
+    OpenBus,
+    /// port of the device with given index
+    Device(u16),
 }
 
-impl InMemoryFile {
-    pub fn new(filename: String) -> Self {
-        Self {
-            filename,
-            data: vec![],
-        }
-    }
+struct MappedDevice {
+    base: u16,
+    device: Box<dyn Device>,
+    /// last NMI output level, to detect the rising edge
+    nmi: bool,
 }
 
-pub struct System {
+pub(crate) struct System {
     finished: bool,
+    exit_code: u8,
     cycle_cnt: u64,
-    cycle_cnt_save: u64,
-    pub mem: [u8; 65536],
+    /// last value seen on the data bus, returned by open bus reads
+    data_bus: u8,
+    pub(crate) interrupts: InterruptLines,
+    pub(crate) console: Rc<RefCell<ConsoleOutput>>,
+    devices: Vec<MappedDevice>,
+    slots: Vec<Slot>,
+    /// CPU data accesses, collected while tracing
+    accesses: Option<Vec<Access>>,
+    /// CPU accesses to device ports, collected for the timeline
+    io_accesses: Option<Vec<Access>>,
+    /// CPU accesses to the zero page, collected for `ZeroPageStats`
+    zp_accesses: Option<Vec<Access>>,
+    /// initial memory contents (ROM images)
+    initial_mem: Vec<u8>,
+    pub(crate) mem: [u8; 65536],
 }
 
 impl Default for System {
     fn default() -> Self {
-        Self {
+        Self::new(&Machine::default())
+    }
+}
+
+impl System {
+    pub fn new(machine: &Machine) -> Self {
+        let mut slots = vec![Slot::OpenBus; 0x10000];
+        let mut initial_mem = vec![0; 0x10000];
+        let mut fill = |start: u16, size: u32, slot: Slot| {
+            for slot_ref in &mut slots[start as usize..start as usize + size as usize] {
+                *slot_ref = slot;
+            }
+        };
+        for region in &machine.ram {
+            fill(region.start, region.size, Slot::Ram);
+        }
+        for region in &machine.rom {
+            fill(region.start, region.size, Slot::Rom);
+        }
+        for region in &machine.open_bus {
+            fill(region.start, region.size, Slot::OpenBus);
+        }
+        for region in &machine.rom {
+            let start = region.start as usize;
+            initial_mem[start..start + region.data.len()].copy_from_slice(&region.data);
+        }
+
+        let mut system = Self {
             finished: false,
+            exit_code: 0,
             cycle_cnt: 0,
-            cycle_cnt_save: 0,
+            data_bus: 0,
+            interrupts: Default::default(),
+            console: Default::default(),
+            devices: vec![],
+            slots,
+            accesses: None,
+            io_accesses: None,
+            zp_accesses: None,
+            initial_mem,
             mem: [0; 65536],
+        };
+        system.mem.copy_from_slice(&system.initial_mem);
+
+        let devices = &machine.devices;
+        let built_in: [(Option<u16>, Box<dyn Device>); 4] = [
+            (devices.clock, Box::new(Clock::default())),
+            (devices.timer, Box::new(Timer::default())),
+            (devices.exit, Box::new(Exit::default())),
+            (
+                devices.console,
+                Box::new(Console::new(system.console.clone())),
+            ),
+        ];
+        for (base, device) in built_in {
+            if let Some(base) = base {
+                system.attach_device(base, device);
+            }
+        }
+        system
+    }
+
+    /// Maps `device` at `base`, on top of whatever was there before.
+    pub fn attach_device(&mut self, base: u16, device: Box<dyn Device>) {
+        let idx = self.devices.len() as u16;
+        for offset in 0..device.size() {
+            self.slots[base.wrapping_add(offset) as usize] = Slot::Device(idx);
+        }
+        self.devices.push(MappedDevice {
+            base,
+            device,
+            nmi: false,
+        });
+    }
+
+    /// Restores power-on state: initial memory contents, cycle counter,
+    /// interrupt inputs and devices.
+    pub fn reset(&mut self) {
+        self.finished = false;
+        self.exit_code = 0;
+        self.cycle_cnt = 0;
+        self.data_bus = 0;
+        self.interrupts = Default::default();
+        self.console.borrow_mut().data.clear();
+        self.mem.copy_from_slice(&self.initial_mem);
+        for mapped in &mut self.devices {
+            mapped.device.reset();
+            mapped.nmi = false;
+        }
+    }
+
+    /// advances the cycle counter and the devices clocked by it
+    pub fn tick(&mut self, cycles: u64) {
+        self.cycle_cnt += cycles;
+        for mapped in &mut self.devices {
+            mapped.device.tick(cycles);
+        }
+        self.update_device_outputs();
+    }
+
+    /// samples interrupt and exit outputs of all devices
+    fn update_device_outputs(&mut self) {
+        let mut irq = false;
+        for mapped in &mut self.devices {
+            let device = &mapped.device;
+            irq |= device.irq();
+            let nmi = device.nmi();
+            if nmi && !mapped.nmi {
+                self.interrupts.request(Interrupt::Nmi);
+            }
+            mapped.nmi = nmi;
+            if let Some(status) = device.exit_status().filter(|_| !self.finished) {
+                self.finished = true;
+                self.exit_code = status;
+            }
+        }
+        self.interrupts.set_irq_line(DEVICES_IRQ_LINE, irq);
+    }
+
+    pub fn slot(&self, address: u16) -> Slot {
+        self.slots[address as usize]
+    }
+
+    /// name of the device mapped at `address`
+    pub fn device_name(&self, address: u16) -> Option<&str> {
+        match self.slot(address) {
+            Slot::Device(idx) => Some(self.devices[idx as usize].device.name()),
+            _ => None,
+        }
+    }
+
+    /// Debugger view of `address`: returns what a CPU read would, without
+    /// the side effects (latching the cycle counter).
+    pub fn peek(&self, address: u16) -> u8 {
+        match self.slot(address) {
+            Slot::Ram | Slot::Rom => self.mem[address as usize],
+            Slot::OpenBus => self.data_bus,
+            Slot::Device(idx) => {
+                let mapped = &self.devices[idx as usize];
+                mapped.device.peek(address.wrapping_sub(mapped.base))
+            }
+        }
+    }
+
+    /// Debugger write to `address`. Ports are left alone, so the debugger
+    /// can't end the program or produce output by writing memory. ROM can be
+    /// written, so programs can be loaded into it.
+    pub fn poke(&mut self, address: u16, data: u8) {
+        match self.slot(address) {
+            Slot::Ram | Slot::Rom => self.mem[address as usize] = data,
+            Slot::OpenBus | Slot::Device(_) => {}
         }
     }
 }
 
 impl Interface6502 for System {
     fn read(&mut self, address: u16) -> u8 {
-        match address {
-            0xfff0 => {
-                self.cycle_cnt_save = self.cycle_cnt;
-                (self.cycle_cnt_save & 0xff) as u8
+        let data = match self.slot(address) {
+            Slot::Device(idx) => {
+                let mapped = &mut self.devices[idx as usize];
+                let data = mapped.device.read(address.wrapping_sub(mapped.base));
+                self.update_device_outputs();
+                if let Some(io_accesses) = &mut self.io_accesses {
+                    io_accesses.push(Access {
+                        addr: address,
+                        data,
+                        write: false,
+                    });
+                }
+                data
             }
-            0xfff1 => ((self.cycle_cnt_save >> 8) & 0xff) as u8,
-            0xfff2 => ((self.cycle_cnt_save >> 16) & 0xff) as u8,
-            0xfff3 => ((self.cycle_cnt_save >> 24) & 0xff) as u8,
-            _ => self.mem[address as usize]
+            _ => self.peek(address),
+        };
+        self.data_bus = data;
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push(Access {
+                addr: address,
+                data,
+                write: false,
+            });
+        }
+        if let Some(zp_accesses) = self.zp_accesses.as_mut().filter(|_| address < 0x100) {
+            zp_accesses.push(Access {
+                addr: address,
+                data,
+                write: false,
+            });
         }
+        data
     }
 
     fn write(&mut self, address: u16, data: u8) {
-        match address {
-            0xfff9 => {
-                eprint!("{}", (data & 0x7f) as char);
-            }
-            0xfff8 => {
-                self.finished = true;
-            }
-            _ => {
-                self.mem[address as usize] = data;
+        self.data_bus = data;
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push(Access {
+                addr: address,
+                data,
+                write: true,
+            });
+        }
+        if let Some(zp_accesses) = self.zp_accesses.as_mut().filter(|_| address < 0x100) {
+            zp_accesses.push(Access {
+                addr: address,
+                data,
+                write: true,
+            });
+        }
+        match self.slot(address) {
+            Slot::Ram => self.mem[address as usize] = data,
+            Slot::Rom | Slot::OpenBus => {}
+            Slot::Device(idx) => {
+                let mapped = &mut self.devices[idx as usize];
+                mapped.device.write(address.wrapping_sub(mapped.base), data);
+                self.update_device_outputs();
+                if let Some(io_accesses) = &mut self.io_accesses {
+                    io_accesses.push(Access {
+                        addr: address,
+                        data,
+                        write: true,
+                    });
+                }
             }
         }
     }
@@ -89,8 +377,44 @@ pub struct Emu {
     pub(crate) cpu: MOS6502,
     pub(crate) watchpoints: Vec<u16>,
     pub(crate) breakpoints: Vec<u16>,
-    pub(crate) files: HashMap<u32, InMemoryFile>,
-    pub(crate) im_reg_map: Option<[usize; 32]>,
+    pub(crate) files: Vfs,
+    /// path (in `files`) of the ELF currently loaded, reported as exec-file
+    pub(crate) exec_file: Option<String>,
+    pub(crate) im_reg_map: ImRegMap,
+    /// imaginary register locations given on the command line, used instead
+    /// of the ones found in the ELF
+    pub(crate) im_reg_override: Option<ImRegMap>,
+    /// separate ELF with debug symbols, for stripped binaries
+    pub(crate) symbol_file: Option<Vec<u8>>,
+    pub(crate) symbols: Symbols,
+    /// interrupts to request at given cycle, sorted by cycle
+    pub(crate) interrupt_schedule: Vec<(u64, Interrupt)>,
+    pub(crate) machine: Machine,
+    /// shared with the debugger connection, see `debugger_connection`
+    pub(crate) memory_map: Rc<RefCell<MemoryMap>>,
+    pub(crate) trace: Trace,
+    pub(crate) history: History,
+    /// print `history` when the program crashes
+    pub(crate) report_history: bool,
+    /// history was printed since the program was loaded
+    pub(crate) history_reported: bool,
+    pub(crate) calls: CallStack,
+    pub(crate) profiler: Profiler,
+    /// source lines of the loaded program, from DWARF
+    pub(crate) lines: LineTable,
+    pub(crate) coverage: Coverage,
+    /// llvm instrumentation counters of the loaded program
+    pub(crate) prof_sections: ProfSections,
+    pub(crate) sampler: Sampler,
+    pub(crate) timeline: Timeline,
+    pub(crate) opcode_stats: OpcodeStats,
+    pub(crate) zp_stats: ZeroPageStats,
+    pub(crate) stack_usage: StackUsage,
+    pub(crate) stack_check: StackCheck,
+    /// address of the instruction running
+    pub(crate) insn_pc: u16,
+    /// ELF the debug info of the loaded program is in
+    pub(crate) debug_elf: Vec<u8>,
 }
 
 impl Default for Emu {
@@ -103,30 +427,82 @@ impl Default for Emu {
             watchpoints: Default::default(),
             breakpoints: Default::default(),
             files: Default::default(),
-            im_reg_map: None,
+            exec_file: None,
+            im_reg_map: Default::default(),
+            im_reg_override: None,
+            symbol_file: None,
+            symbols: Default::default(),
+            interrupt_schedule: Default::default(),
+            machine: Machine::default(),
+            memory_map: Default::default(),
+            trace: Default::default(),
+            history: Default::default(),
+            report_history: false,
+            history_reported: false,
+            calls: Default::default(),
+            profiler: Default::default(),
+            lines: Default::default(),
+            coverage: Default::default(),
+            prof_sections: Default::default(),
+            sampler: Default::default(),
+            timeline: Default::default(),
+            opcode_stats: Default::default(),
+            zp_stats: Default::default(),
+            stack_usage: Default::default(),
+            stack_check: Default::default(),
+            insn_pc: 0,
+            debug_elf: vec![],
         }
     }
 }
 
 impl Emu {
+    /// Sample period used when none is given, see `set_sample_period`
+    pub const DEFAULT_SAMPLE_PERIOD: u64 = crate::autofdo::DEFAULT_SAMPLE_PERIOD;
+
+    /// Emulator of `machine`, with nothing loaded yet.
+    pub fn new(machine: Machine) -> DynResult<Self> {
+        let mut emu = Self::default();
+        emu.set_machine(machine)?;
+        Ok(emu)
+    }
+
     pub fn load_elf(&mut self, program_elf: &[u8]) -> DynResult<()> {
         // load ELF
         let elf_header = goblin::elf::Elf::parse(program_elf)?;
-        self.im_reg_map = None;
-        for sym in elf_header.syms.iter() {
-            let sym_name = elf_header.strtab.get_at(sym.st_name).unwrap_or("");
-            // println!("HERE: {:?} {}", sym_name, st_bind(sym.st_info) == STB_GLOBAL);
-            if sym_name.starts_with("__rc") {
-                if let Ok(idx) = sym_name[4..].parse::<usize>() {
-                    if idx < 32 && sym.st_value < 256 {
-                        let im_reg_map = self.im_reg_map.get_or_insert_with(|| [0; 32]);
-                        im_reg_map[idx] = sym.st_value as usize;
-                        log::info!("immaginary reg mapping: {} -> {:02x?}", sym_name, sym.st_value);
-                    } else {
-                        log::warn!("invalid immaginary reg mapping: {} -> {:04x?}", sym_name, sym.st_value);
-                    }
+        let symbol_elf = match &self.symbol_file {
+            Some(symbol_file) => Some(goblin::elf::Elf::parse(symbol_file)?),
+            None => None,
+        };
+        self.symbols = Symbols::from_elf(&elf_header);
+        if let Some(symbol_elf) = &symbol_elf {
+            self.symbols.merge(&Symbols::from_elf(symbol_elf));
+        }
+        // debug info is in the symbol file for stripped programs
+        let (line_elf, line_data) = match (&symbol_elf, &self.symbol_file) {
+            (Some(symbol_elf), Some(symbol_file)) => (symbol_elf, symbol_file.as_slice()),
+            _ => (&elf_header, program_elf),
+        };
+        self.debug_elf = line_data.to_vec();
+        self.lines = LineTable::from_elf(line_elf, line_data).unwrap_or_else(|err| {
+            log::warn!("reading DWARF line table failed: {}", err);
+            Default::default()
+        });
+        for name in self.trace.resolve_filter(&self.symbols) {
+            log::warn!("trace filter: no function {}", name);
+        }
+        self.im_reg_map = match &self.im_reg_override {
+            Some(im_reg_map) => im_reg_map.clone(),
+            None => {
+                let mut im_reg_map = ImRegMap::from_elf(&elf_header);
+                if let Some(symbol_elf) = &symbol_elf {
+                    im_reg_map.merge(&ImRegMap::from_elf(symbol_elf));
                 }
+                im_reg_map
             }
+        };
+        if self.im_reg_map.is_empty() {
+            log::warn!("no imaginary registers found, use --im-regs to specify them");
         }
 
         // copy all in-memory sections from the ELF file into system RAM
@@ -135,33 +511,645 @@ impl Emu {
             .iter()
             .filter(|h| h.is_alloc() && h.sh_type != goblin::elf::section_header::SHT_NOBITS);
 
-        self.system = System::default();
+        self.prof_sections = ProfSections::from_elf(&elf_header);
+
+        self.system.reset();
+        *self.memory_map.borrow_mut() = MemoryMap::new(&self.system, Some(&elf_header));
 
         for h in sections {
-            eprintln!(
+            log::info!(
                 "loading section {:?} into memory from [{:#010x?}..{:#010x?}]",
                 elf_header.shdr_strtab.get_at(h.sh_name).unwrap(),
                 h.sh_addr,
                 h.sh_addr + h.sh_size,
             );
 
-            for (i, b) in program_elf[h.file_range().unwrap()].iter().enumerate() {
-                self.system.write(h.sh_addr as u16 + i as u16, *b);
+            let data = &program_elf[h.file_range().unwrap()];
+            let addrs = (0..data.len()).map(|i| h.sh_addr as u16 + i as u16);
+            if addrs
+                .clone()
+                .any(|addr| !matches!(self.system.slot(addr), Slot::Ram | Slot::Rom))
+            {
+                log::warn!("section is not entirely backed by RAM or ROM of the machine");
+            }
+            for (addr, b) in addrs.zip(data) {
+                self.system.poke(addr, *b);
             }
         }
 
         self.cpu.set_program_counter(elf_header.entry as u16);
-        eprintln!("PC: {:04x}", elf_header.entry as u16);
+        log::info!("PC: {:04x}", elf_header.entry as u16);
+        self.reset_collectors(self.cpu.get_program_counter());
         self.watchpoints = Default::default();
         self.breakpoints = Default::default();
-        self.files = Default::default();
+        self.exec_file = None;
         self.exec_mode = ExecMode::Continue;
 
         Ok(())
     }
 
-    // pub(crate) fn reset(&mut self) {
-    // }
+    /// Replaces the machine description. Memory contents and devices
+    /// attached with `attach_device` are lost, so this is meant to be called
+    /// before setting up anything else.
+    pub fn set_machine(&mut self, machine: Machine) -> DynResult<()> {
+        machine.validate()?;
+        log::info!("cpu model: {:?}", machine.cpu);
+        let echo = self.system.console.borrow().echo;
+        self.system = System::new(&machine);
+        self.system.console.borrow_mut().echo = echo;
+        if self.timeline.enabled() {
+            self.system.io_accesses = Some(vec![]);
+        }
+        if self.zp_stats.enabled() {
+            self.system.zp_accesses = Some(vec![]);
+        }
+        if let Some(base) = machine.devices.trace {
+            let port = self.trace.control_port();
+            self.system.attach_device(base, Box::new(port));
+        }
+        *self.memory_map.borrow_mut() = MemoryMap::new(&self.system, None);
+        self.machine = machine;
+        Ok(())
+    }
+
+    /// Maps a custom peripheral at `base`. Devices stay attached when a new
+    /// program is loaded (they are `reset` instead).
+    pub fn attach_device(&mut self, base: u16, device: Box<dyn Device>) {
+        self.system.attach_device(base, device);
+        *self.memory_map.borrow_mut() = MemoryMap::new(&self.system, None);
+    }
+
+    /// load ELF from the host filesystem, keeping a copy in `files` so the
+    /// debugger can fetch it as the exec-file
+    pub fn load_elf_file(&mut self, path: &str) -> DynResult<()> {
+        let path = std::fs::canonicalize(path)?.to_string_lossy().into_owned();
+        let data = std::fs::read(&path)?;
+        self.load_elf(&data)?;
+        self.files.create(&path, data);
+        self.exec_file = Some(path);
+        Ok(())
+    }
+
+    /// Loads raw binary `image` at `base` and sets PC to `entry`.
+    pub fn load_image(&mut self, base: u16, image: &[u8], entry: u16) {
+        self.system.reset();
+        for (i, b) in image.iter().enumerate() {
+            self.system.poke(base.wrapping_add(i as u16), *b);
+        }
+        *self.memory_map.borrow_mut() = MemoryMap::new(&self.system, None);
+        self.im_reg_map = self.im_reg_override.clone().unwrap_or_default();
+        self.symbols = Default::default();
+        self.lines = Default::default();
+        self.prof_sections = Default::default();
+        self.debug_elf = vec![];
+        self.cpu.set_program_counter(entry);
+        self.reset_collectors(self.cpu.get_program_counter());
+        self.watchpoints = Default::default();
+        self.breakpoints = Default::default();
+        self.exec_file = None;
+        self.exec_mode = ExecMode::Continue;
+    }
+
+    /// Drops the history and the data of every profiler for a new program
+    /// starting at `pc`.
+    fn reset_collectors(&mut self, pc: u16) {
+        self.history.clear();
+        self.history_reported = false;
+        self.calls.reset(pc);
+        self.profiler.reset(pc);
+        self.coverage.reset();
+        self.opcode_stats.reset();
+        self.zp_stats.reset();
+        self.sampler.reset(0);
+        self.timeline.reset(pc, 0);
+        self.stack_usage.reset(self.symbols.addr("__stack"));
+        self.stack_check.reset();
+    }
+
+    /// Separate ELF with the symbols of stripped programs loaded afterwards.
+    pub fn set_symbol_file(&mut self, data: Vec<u8>) {
+        self.symbol_file = Some(data);
+    }
+
+    /// Imaginary register locations to use instead of the ones found in
+    /// loaded programs.
+    pub fn set_im_regs(&mut self, im_reg_map: ImRegMap) {
+        self.im_reg_override = Some(im_reg_map);
+    }
+
+    /// Runs until the program exits, hits a breakpoint or `max_cycles`
+    /// elapse.
+    pub fn run_for(&mut self, max_cycles: u64) -> StopReason {
+        let limit = self.system.cycle_cnt.saturating_add(max_cycles);
+        while self.system.cycle_cnt < limit {
+            match self.step() {
+                Some(Event::Halted) => return StopReason::Exited(self.system.exit_code),
+                Some(Event::Break) => {
+                    return StopReason::Breakpoint(self.cpu.get_program_counter())
+                }
+                Some(Event::StackFault(fault)) => return StopReason::StackFault(fault),
+                _ => {}
+            }
+        }
+        self.report_history("cycle limit reached");
+        StopReason::CycleLimit
+    }
+
+    /// Sets a breakpoint for `run_for`.
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.push(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.retain(|bp| *bp != addr);
+    }
+
+    /// cycles elapsed since the program was loaded
+    pub fn cycles(&self) -> u64 {
+        self.system.cycle_cnt
+    }
+
+    /// exit status, once the program has finished
+    pub fn exit_code(&self) -> Option<u8> {
+        if self.system.finished {
+            Some(self.system.exit_code)
+        } else {
+            None
+        }
+    }
+
+    /// Console output of the program so far.
+    pub fn output(&self) -> Vec<u8> {
+        self.system.console.borrow().data.clone()
+    }
+
+    /// Returns console output of the program so far and clears it.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.system.console.borrow_mut().data)
+    }
+
+    /// Also print console output to stderr as it is written.
+    pub fn set_console_echo(&mut self, echo: bool) {
+        self.system.console.borrow_mut().echo = echo;
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            pc: self.cpu.get_program_counter(),
+            a: self.cpu.get_accumulator(),
+            x: self.cpu.get_x_register(),
+            y: self.cpu.get_y_register(),
+            s: self.cpu.get_stack_pointer(),
+            p: self.cpu.get_status_register(),
+        }
+    }
+
+    pub fn set_registers(&mut self, regs: &Registers) {
+        self.cpu.set_program_counter(regs.pc);
+        self.cpu.set_accumulator(regs.a);
+        self.cpu.set_x_register(regs.x);
+        self.cpu.set_y_register(regs.y);
+        self.cpu.set_stack_pointer(regs.s);
+        self.cpu.set_status_register(regs.p);
+    }
+
+    /// imaginary register `__rcN`, if its location is known
+    pub fn im_reg(&self, idx: usize) -> Option<u8> {
+        Some(self.system.peek(self.im_reg_map.get(idx)?))
+    }
+
+    /// Sets imaginary register `__rcN`, returns false if its location isn't known.
+    pub fn set_im_reg(&mut self, idx: usize, val: u8) -> bool {
+        match self.im_reg_map.get(idx) {
+            Some(addr) => {
+                self.system.poke(addr, val);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Starts or stops logging executed instructions.
+    pub fn set_trace(&mut self, enabled: bool) {
+        self.trace.set_enabled(enabled);
+    }
+
+    /// Where trace lines go, stderr by default.
+    pub fn set_trace_output(&mut self, output: Box<dyn std::io::Write>) {
+        self.trace.set_output(output);
+    }
+
+    /// Restricts the trace to address ranges (`START-END`) and functions,
+    /// given as a comma separated list. Returns names of functions not found
+    /// in the loaded program; they are looked up again on the next load.
+    pub fn set_trace_filter(&mut self, spec: &str) -> Result<Vec<String>, String> {
+        self.trace.set_filter(spec)?;
+        Ok(self.trace.resolve_filter(&self.symbols))
+    }
+
+    /// Number of recent instructions kept for `history`, 0 disables it.
+    pub fn set_history_len(&mut self, len: usize) {
+        self.history = History::new(len);
+    }
+
+    /// Print the history to stderr when the program executes `BRK` or an
+    /// undocumented opcode, exits with a non-zero status or runs out of
+    /// cycles in `run_for`. Only the first of these is reported.
+    pub fn set_report_history(&mut self, report: bool) {
+        self.report_history = report;
+    }
+
+    /// Last `count` executed instructions, oldest first, in trace format.
+    /// Instructions are disassembled from current memory.
+    pub fn history(&self, count: usize) -> Vec<String> {
+        let entries: Vec<_> = self.history.iter().rev().take(count).collect();
+        entries
+            .into_iter()
+            .rev()
+            .map(|(cycle, regs)| {
+                let entry = TraceEntry {
+                    cycle: *cycle,
+                    regs: *regs,
+                    insn: self.disassemble_one(regs.pc),
+                    accesses: vec![],
+                };
+                entry.format(&self.symbols, &self.im_reg_map)
+            })
+            .collect()
+    }
+
+    fn report_history(&mut self, reason: &str) {
+        if !self.report_history || self.history_reported {
+            return;
+        }
+        self.history_reported = true;
+        let pc = self.cpu.get_program_counter();
+        let location = self.symbols.symbolize(pc).map(|sym| format!(" <{}>", sym));
+        let lines = self.history(usize::MAX);
+        eprintln!(
+            "{} at {:04x}{}, last {} instructions:",
+            reason,
+            pc,
+            location.unwrap_or_default(),
+            lines.len()
+        );
+        for line in lines {
+            eprintln!("{}", line);
+        }
+    }
+
+    /// Starts or stops the function profiler. Data collected so far is kept
+    /// until the next program is loaded or `reset_profile`.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiler
+            .set_enabled(enabled, self.cpu.get_program_counter());
+    }
+
+    /// Drops collected profile, the current function becomes the outermost.
+    pub fn reset_profile(&mut self) {
+        self.profiler.reset(self.cpu.get_program_counter());
+    }
+
+    /// Inclusive / exclusive cycles and calls per function, as a table.
+    pub fn profile_report(&self) -> String {
+        self.profiler.report(&self.symbols)
+    }
+
+    /// Writes call graph and cycles per function in callgrind format.
+    pub fn write_callgrind(&self, out: &mut dyn std::io::Write) -> std::io::Result<()> {
+        self.profiler.write_callgrind(&self.symbols, out)
+    }
+
+    /// Starts or stops counting executions and cycles per source line.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage.set_enabled(enabled);
+    }
+
+    pub fn reset_coverage(&mut self) {
+        self.coverage.reset();
+    }
+
+    /// Execution count and cycles of every source line with code, as
+    /// `(file, line, stats)`.
+    pub fn line_profile(&self) -> Vec<(String, u32, LineStats)> {
+        let stats = self.coverage.line_stats(&self.lines);
+        self.lines
+            .locs
+            .iter()
+            .zip(stats)
+            .map(|((file, line), stats)| (self.lines.files[*file as usize].clone(), *line, stats))
+            .collect()
+    }
+
+    /// Writes line coverage in lcov format.
+    pub fn write_lcov(&self, out: &mut dyn std::io::Write) -> std::io::Result<()> {
+        self.coverage.write_lcov(&self.lines, out)
+    }
+
+    /// Writes source files annotated with execution counts and cycles.
+    pub fn write_annotated_source(&self, out: &mut dyn std::io::Write) -> std::io::Result<()> {
+        self.coverage.write_annotated(&self.lines, out)
+    }
+
+    /// Starts or stops counting executions and cycles per opcode, broken
+    /// down by function if `per_function`.
+    pub fn set_opcode_stats(&mut self, enabled: bool, per_function: bool) {
+        self.opcode_stats.set_enabled(enabled, per_function);
+    }
+
+    pub fn reset_opcode_stats(&mut self) {
+        self.opcode_stats.reset();
+    }
+
+    /// Executions and cycles per opcode and addressing mode, as tables.
+    pub fn opcode_report(&self) -> String {
+        self.opcode_stats.report(&self.symbols)
+    }
+
+    /// Writes executions and cycles per opcode and addressing mode as JSON.
+    pub fn write_opcode_json(&self, out: &mut dyn std::io::Write) -> std::io::Result<()> {
+        self.opcode_stats.write_json(&self.symbols, out)
+    }
+
+    /// Starts or stops counting zero page reads and writes per function.
+    pub fn set_zp_stats(&mut self, enabled: bool) {
+        self.zp_stats.set_enabled(enabled);
+        self.system.zp_accesses = enabled.then(Vec::new);
+    }
+
+    pub fn reset_zp_stats(&mut self) {
+        self.zp_stats.reset();
+    }
+
+    /// Zero page traffic by imaginary register and function, as tables.
+    pub fn zp_report(&self) -> String {
+        self.zp_stats.report(&self.symbols, &self.im_reg_map)
+    }
+
+    /// Starts or stops tracking the lowest hardware and soft stack pointers.
+    pub fn set_stack_usage(&mut self, enabled: bool) {
+        self.stack_usage.set_enabled(enabled);
+    }
+
+    /// Stops execution with `Event::StackFault` on stack overflow and
+    /// underflow, returns not going back to where the call came from and
+    /// return addresses overwritten by stores.
+    pub fn set_stack_check(&mut self, enabled: bool) {
+        self.stack_check.set_enabled(enabled);
+    }
+
+    /// Peak hardware and soft stack depth, with the call chains that reached
+    /// them.
+    pub fn stack_usage_report(&self) -> String {
+        self.stack_usage.report(&self.symbols)
+    }
+
+    /// `file:line` of `addr`, from DWARF line information.
+    pub fn source_location(&self, addr: u16) -> Option<String> {
+        self.lines.location(addr)
+    }
+
+    /// whether the program was built with `-fprofile-instr-generate`
+    pub fn is_instrumented(&self) -> bool {
+        self.prof_sections.is_present()
+    }
+
+    /// Writes the llvm instrumentation counters of the program as a
+    /// `.profraw` file, for `llvm-profdata merge`.
+    pub fn write_profraw(&self, out: &mut dyn std::io::Write) -> DynResult<()> {
+        let version_addr = self
+            .symbols
+            .addr("__llvm_profile_raw_version")
+            .ok_or("no __llvm_profile_raw_version symbol")?;
+        let mut version = [0; 8];
+        self.read_memory(version_addr, &mut version);
+        crate::profraw::write_profraw(
+            &self.prof_sections,
+            u64::from_le_bytes(version),
+            |addr| self.system.peek(addr),
+            out,
+        )
+    }
+
+    /// Samples the PC every `period` cycles for `write_sample_profile`, 0
+    /// stops sampling.
+    pub fn set_sample_period(&mut self, period: u64) {
+        self.sampler.set_period(period, self.system.cycle_cnt);
+    }
+
+    /// Drops the PC samples taken so far.
+    pub fn reset_samples(&mut self) {
+        self.sampler.reset(self.system.cycle_cnt);
+    }
+
+    /// Writes the PC samples in llvm's sample profile text format, for
+    /// `-fprofile-sample-use`. Needs DWARF debug info in the program (or
+    /// symbol file).
+    pub fn write_sample_profile(&self, out: &mut dyn std::io::Write) -> DynResult<()> {
+        let elf = goblin::elf::Elf::parse(&self.debug_elf)?;
+        crate::autofdo::write_sample_profile(&elf, &self.debug_elf, self.sampler.samples(), out)
+    }
+
+    /// Starts or stops recording function calls, device accesses and
+    /// interrupts for `write_timeline`. Events recorded so far are kept
+    /// until the next program is loaded.
+    pub fn set_timeline(&mut self, enabled: bool) {
+        let pc = self.cpu.get_program_counter();
+        self.timeline
+            .set_enabled(enabled, pc, self.system.cycle_cnt);
+        self.system.io_accesses = enabled.then(Vec::new);
+    }
+
+    /// Writes the recorded timeline as Chrome trace event JSON, for Perfetto
+    /// or `chrome://tracing`. Cycles are converted to microseconds at the
+    /// `clock_hz` of the machine.
+    pub fn write_timeline(&self, out: &mut dyn std::io::Write) -> std::io::Result<()> {
+        self.timeline.write_json(
+            &self.symbols,
+            self.machine.clock_hz,
+            self.system.cycle_cnt,
+            out,
+        )
+    }
+
+    /// Address of symbol `name` of the loaded program.
+    pub fn symbol_addr(&self, name: &str) -> Option<u16> {
+        self.symbols.addr(name)
+    }
+
+    /// Resolves symbol name or number (decimal, `0x` or `$` hex) to an
+    /// address.
+    pub fn resolve(&self, location: &str) -> Result<u16, String> {
+        if let Some(addr) = self.symbols.addr(location) {
+            return Ok(addr);
+        }
+        match crate::im_regs::parse_num(location) {
+            Ok(addr) if addr <= 0xffff => Ok(addr as u16),
+            _ => Err(format!("no symbol or invalid address: {}", location)),
+        }
+    }
+
+    /// Disassembles `count` instructions from `addr`, as listing lines
+    /// preceded by labels for symbols starting there.
+    pub fn disassemble(&self, addr: u16, count: usize) -> Vec<String> {
+        let mut lines = vec![];
+        let mut addr = addr;
+        for _ in 0..count {
+            let insn = self.disassemble_one(addr);
+            lines.extend(
+                self.symbols
+                    .at(addr)
+                    .map(|sym| format!("{:04x} <{}>:", addr, sym.name)),
+            );
+            lines.push(insn.line(&self.symbols, &self.im_reg_map));
+            addr = addr.wrapping_add(insn.size());
+        }
+        lines
+    }
+
+    /// Disassembles instructions starting in the `len` bytes from `start`,
+    /// see `disassemble`.
+    pub fn disassemble_range(&self, start: u16, len: u16) -> Vec<String> {
+        let mut lines = vec![];
+        let mut addr = start as u32;
+        while addr < start as u32 + len as u32 {
+            let insn = self.disassemble_one(addr as u16);
+            lines.extend(self.disassemble(addr as u16, 1));
+            addr += insn.size() as u32;
+        }
+        lines
+    }
+
+    pub fn disassemble_one(&self, addr: u16) -> Instruction {
+        Instruction::decode(addr, |addr| self.system.peek(addr))
+    }
+
+    /// Reads memory the way a debugger does, without side effects on devices.
+    pub fn read_memory(&self, addr: u16, buf: &mut [u8]) {
+        for (i, val) in buf.iter_mut().enumerate() {
+            *val = self.system.peek(addr.wrapping_add(i as u16));
+        }
+    }
+
+    /// Writes memory (including ROM) the way a debugger does, devices are
+    /// left alone.
+    pub fn write_memory(&mut self, addr: u16, data: &[u8]) {
+        for (i, val) in data.iter().enumerate() {
+            self.system.poke(addr.wrapping_add(i as u16), *val);
+        }
+    }
+
+    fn poll_interrupts(&mut self) {
+        let cycles = self.system.cycle_cnt;
+        let due = self
+            .interrupt_schedule
+            .iter()
+            .take_while(|(cycle, _)| *cycle <= cycles)
+            .count();
+        for (_, interrupt) in self.interrupt_schedule.drain(..due) {
+            self.system.interrupts.request(interrupt);
+        }
+
+        let irq_disabled = self.cpu.get_status_register() & 0x04 != 0;
+        if let Some(interrupt) = self.system.interrupts.take(irq_disabled) {
+            self.enter_interrupt(interrupt);
+        }
+    }
+
+    /// push PC and P, then jump through the interrupt vector, the way the
+    /// 6502 does it (7 cycles)
+    fn enter_interrupt(&mut self, interrupt: Interrupt) {
+        let pc = self.cpu.get_program_counter();
+        let flags = self.cpu.get_status_register();
+        // B flag clear, so the handler can tell it apart from BRK
+        for val in [(pc >> 8) as u8, pc as u8, (flags & !0x10) | 0x20] {
+            let sp = self.cpu.get_stack_pointer();
+            self.system.write(0x100 | sp as u16, val);
+            self.cpu.set_stack_pointer(sp.wrapping_sub(1));
+        }
+        self.cpu.set_status_register(flags | 0x04);
+
+        let vector = interrupt.vector();
+        let handler = u16::from_le_bytes([self.system.peek(vector), self.system.peek(vector + 1)]);
+        log::debug!("{:?} at {:04x}, handler {:04x}", interrupt, pc, handler);
+        self.cpu.set_program_counter(handler);
+        let cycle = self.system.cycle_cnt;
+        self.system.tick(7);
+        if self.timeline.enabled() {
+            let args = format!("{{\"pc\":\"{:#06x}\"}}", pc);
+            let name = format!("{:?}", interrupt).to_uppercase();
+            self.timeline.instant(name, "interrupt", Some(args), cycle);
+        }
+        // interrupted function's stack pointer, before the push
+        let sp = self.cpu.get_stack_pointer().wrapping_add(3);
+        self.enter_function(handler, sp, cycle);
+        if self.profiler.enabled() {
+            self.profiler.instruction(7);
+        }
+        if self.stack_check.enabled() {
+            let system = &self.system;
+            let new_sp = self.cpu.get_stack_pointer();
+            self.stack_check
+                .interrupt(sp, new_sp, |addr| system.peek(addr));
+        }
+        if self.stack_usage.enabled() {
+            self.update_stack_usage(handler);
+        }
+    }
+
+    /// Function `func` was called with the stack pointer at `sp` before the
+    /// call, at `cycle`.
+    pub(crate) fn enter_function(&mut self, func: u16, sp: u8, cycle: u64) {
+        self.calls.frames.push((func, sp as u16));
+        if self.profiler.enabled() {
+            self.profiler.call(func);
+        }
+        if self.timeline.enabled() {
+            self.timeline.call(func, cycle);
+        }
+    }
+
+    /// The stack pointer went up to `sp` at `cycle`: leaves the functions
+    /// the stack was unwound past.
+    pub(crate) fn leave_functions(&mut self, sp: u8, cycle: u64) {
+        while self.calls.pop_returned(sp) {
+            if self.profiler.enabled() {
+                self.profiler.ret();
+            }
+            if self.timeline.enabled() {
+                self.timeline.ret(cycle);
+            }
+        }
+    }
+
+    /// Instruction at `pc` is done: records the stack pointers if they
+    /// reached a new low.
+    fn update_stack_usage(&mut self, pc: u16) {
+        let soft_sp = self.soft_stack_pointer();
+        self.stack_usage.update(
+            pc,
+            self.cpu.get_stack_pointer(),
+            soft_sp,
+            self.calls.funcs(),
+        );
+    }
+
+    /// current value of the llvm-mos soft stack pointer `__rs0`
+    fn soft_stack_pointer(&self) -> Option<u16> {
+        let (lo, hi) = self.im_reg_map.pair(0)?;
+        Some(u16::from_le_bytes([
+            self.system.peek(lo),
+            self.system.peek(hi),
+        ]))
+    }
+
+    /// request `interrupt` at `cycle`
+    pub fn schedule_interrupt(&mut self, cycle: u64, interrupt: Interrupt) {
+        let pos = self
+            .interrupt_schedule
+            .partition_point(|(at, _)| *at <= cycle);
+        self.interrupt_schedule.insert(pos, (cycle, interrupt));
+    }
 
     /// single-step the interpreter
     pub fn step(&mut self) -> Option<Event> {
@@ -171,13 +1159,116 @@ impl Emu {
         //     hit_watchpoint = Some(access)
         // });
 
+        let pc = self.cpu.get_program_counter();
+        let insn_start = self.cpu.get_remaining_cycles() == 0;
+        let start_cycle = self.system.cycle_cnt;
+        let mut opcode = None;
+        if insn_start {
+            self.insn_pc = pc;
+            opcode = Some((self.system.peek(pc), self.cpu.get_stack_pointer()));
+            self.history.push(self.system.cycle_cnt, self.registers());
+            match self.system.peek(pc) {
+                0x00 => self.report_history("trap (BRK)"),
+                opcode if opcode_info(opcode).is_none() => {
+                    self.report_history(&format!("illegal opcode {:02x}", opcode))
+                }
+                _ => {}
+            }
+        }
+        let mut trace_entry = None;
+        if self.cpu.get_remaining_cycles() == 0 && self.trace.wants(pc) {
+            trace_entry = Some(TraceEntry {
+                cycle: self.system.cycle_cnt,
+                regs: self.registers(),
+                insn: self.disassemble_one(pc),
+                accesses: vec![],
+            });
+            self.system.accesses = Some(vec![]);
+        }
+
         self.cpu.cycle(&mut self.system);
 
-        self.system.cycle_cnt += 1;
+        if let Some(mut entry) = trace_entry {
+            let mut accesses = self.system.accesses.take().unwrap_or_default();
+            let fetch = pc as u32..pc as u32 + entry.insn.size() as u32;
+            accesses.retain(|access| access.write || !fetch.contains(&(access.addr as u32)));
+            entry.accesses = accesses;
+            self.trace.log(&entry, &self.symbols, &self.im_reg_map);
+        }
+
+        if insn_start && self.coverage.enabled() {
+            self.coverage
+                .instruction(pc, self.cpu.get_remaining_cycles() as u64 + 1);
+        }
+        if let Some((opcode, sp)) = opcode {
+            let cycles = self.cpu.get_remaining_cycles() as u64 + 1;
+            if self.opcode_stats.enabled() {
+                self.opcode_stats
+                    .instruction(&self.symbols, pc, opcode, cycles);
+            }
+            if self.profiler.enabled() {
+                self.profiler.instruction(cycles);
+            }
+            match opcode {
+                // JSR, BRK
+                0x20 | 0x00 => {
+                    let func = self.cpu.get_program_counter();
+                    self.enter_function(func, sp, start_cycle);
+                }
+                // RTS, RTI
+                0x60 | 0x40 => {
+                    let sp = self.cpu.get_stack_pointer();
+                    self.leave_functions(sp, start_cycle + cycles);
+                }
+                _ => {}
+            }
+            if self.stack_usage.enabled() {
+                self.update_stack_usage(pc);
+            }
+            if self.stack_check.enabled() {
+                let (new_sp, new_pc) =
+                    (self.cpu.get_stack_pointer(), self.cpu.get_program_counter());
+                let system = &self.system;
+                self.stack_check
+                    .instruction(opcode, sp, new_sp, new_pc, |addr| system.peek(addr));
+            }
+        }
+        if let Some(zp_accesses) = &mut self.system.zp_accesses {
+            for access in zp_accesses.drain(..) {
+                let addr = access.addr as u8;
+                self.zp_stats
+                    .access(&self.symbols, self.insn_pc, addr, access.write);
+            }
+        }
+        if let Some(io_accesses) = &mut self.system.io_accesses {
+            for access in std::mem::take(io_accesses) {
+                let device = self.system.device_name(access.addr).unwrap_or("device");
+                let name = format!("{} {}", device, if access.write { "write" } else { "read" });
+                let args = format!(
+                    "{{\"port\":\"{:#06x}\",\"value\":\"{:#04x}\"}}",
+                    access.addr, access.data
+                );
+                self.timeline.instant(name, "io", Some(args), start_cycle);
+            }
+        }
+
+        self.system.tick(1);
+        if self.sampler.enabled() {
+            self.sampler.tick(self.system.cycle_cnt, self.insn_pc);
+        }
         if self.system.finished {
+            if self.system.exit_code != 0 {
+                let reason = format!("exit status {}", self.system.exit_code);
+                self.report_history(&reason);
+            }
+            self.trace.flush();
             self.exec_mode = ExecMode::Idle;
             return Some(Event::Halted);
         }
+        if self.cpu.get_remaining_cycles() == 0 {
+            // instruction boundary, the only place interrupts are taken
+            self.poll_interrupts();
+        }
         let pc = self.cpu.get_program_counter();
         // self.cpu.step(&mut sniffer);
         // let pc = self.cpu.reg_get(Mode::User, reg::PC);
@@ -192,7 +1283,15 @@ impl Emu {
         //     });
         // }
 
-        if self.breakpoints.contains(&pc) {
+        // only stop before an instruction starts, so that resuming from a
+        // breakpoint makes progress
+        if self.cpu.get_remaining_cycles() == 0 {
+            if let Some(fault) = self.stack_check.take_fault() {
+                self.report_history(&fault.to_string());
+                return Some(Event::StackFault(fault));
+            }
+        }
+        if self.cpu.get_remaining_cycles() == 0 && self.breakpoints.contains(&pc) {
             return Some(Event::Break);
         }
 
@@ -209,7 +1308,7 @@ impl Emu {
     /// will use the provided callback to poll the connection for incoming data
     /// every 1024 steps.
     pub fn run(&mut self, mut poll_incoming_data: impl FnMut() -> bool) -> RunEvent {
-        eprintln!("target run: {:?}", self.exec_mode);
+        log::debug!("target run: {:?}", self.exec_mode);
         match self.exec_mode {
             ExecMode::Idle => loop {
                 if poll_incoming_data() {
@@ -235,7 +1334,7 @@ impl Emu {
             }
             // just continue, but with an extra PC check
             ExecMode::RangeStep(start, end) => {
-                eprintln!("range step");
+                log::debug!("range step");
                 let mut cycles = 0;
                 loop {
                     if cycles % 1024 == 0 {
@@ -0,0 +1,66 @@
+use gdbstub::arch::{lldb, Arch, Registers, SingleStepGdbBehavior};
+use gdbstub_mos_arch::{MOSArch, MosBreakpointKind, MosRegId, MosRegs};
+
+/// Offsets of `RC0` and `RS0` in the `g` packet, see `tools/gen_regs.py`
+const RC0_OFFSET: usize = 10;
+const RS0_OFFSET: usize = RC0_OFFSET + 32;
+
+/// `MOSArch` reporting imaginary registers the ELF doesn't locate as
+/// unavailable, instead of as zero.
+pub enum MosArch {}
+
+impl Arch for MosArch {
+    type Usize = u16;
+    type Registers = MosRegisters;
+    type BreakpointKind = MosBreakpointKind;
+    type RegId = MosRegId;
+
+    fn target_description_xml() -> Option<&'static str> {
+        MOSArch::target_description_xml()
+    }
+
+    fn lldb_register_info(reg_id: usize) -> Option<lldb::RegisterInfo<'static>> {
+        MOSArch::lldb_register_info(reg_id)
+    }
+
+    fn single_step_gdb_behavior() -> SingleStepGdbBehavior {
+        MOSArch::single_step_gdb_behavior()
+    }
+}
+
+/// `MosRegs` along with which of the imaginary registers have a location
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MosRegisters {
+    pub regs: MosRegs,
+    /// bit N set if `__rcN` is mapped
+    pub rc_mapped: u32,
+    /// bit N set if `__rsN` is mapped
+    pub rs_mapped: u16,
+}
+
+impl Registers for MosRegisters {
+    type ProgramCounter = u16;
+
+    fn pc(&self) -> u16 {
+        self.regs.pc
+    }
+
+    fn gdb_serialize(&self, mut write_byte: impl FnMut(Option<u8>)) {
+        let mut offset = 0;
+        self.regs.gdb_serialize(|byte| {
+            let mapped = if offset >= RS0_OFFSET {
+                self.rs_mapped & 1 << ((offset - RS0_OFFSET) / 2) != 0
+            } else if offset >= RC0_OFFSET {
+                self.rc_mapped & 1 << (offset - RC0_OFFSET) != 0
+            } else {
+                true
+            };
+            write_byte(byte.filter(|_| mapped));
+            offset += 1;
+        })
+    }
+
+    fn gdb_deserialize(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        self.regs.gdb_deserialize(bytes)
+    }
+}
@@ -3,6 +3,7 @@ use gdbstub::target::ext::breakpoints::WatchKind;
 use gdbstub::target::TargetResult;
 
 use crate::emu::Emu;
+use crate::memory_map::RegionKind;
 use gdbstub_mos_arch::MosBreakpointKind;
 
 impl target::ext::breakpoints::Breakpoints for Emu {
@@ -13,6 +14,15 @@ impl target::ext::breakpoints::Breakpoints for Emu {
         Some(self)
     }
 
+    #[inline(always)]
+    fn support_hw_breakpoint(
+        &mut self,
+    ) -> Option<target::ext::breakpoints::HwBreakpointOps<'_, Self>> {
+        // GDB falls back to hardware breakpoints in regions the memory map
+        // reports as ROM; both kinds are emulated the same way
+        Some(self)
+    }
+
     #[inline(always)]
     fn support_hw_watchpoint(
         &mut self,
@@ -28,6 +38,9 @@ impl target::ext::breakpoints::SwBreakpoint for Emu {
         addr: u16,
         _kind: MosBreakpointKind,
     ) -> TargetResult<bool, Self> {
+        if self.memory_map.borrow().kind_at(addr) == RegionKind::Io {
+            return Ok(false);
+        }
         self.breakpoints.push(addr);
         eprintln!("Add breakpoint {:04x}", addr);
         Ok(true)
@@ -50,6 +63,24 @@ impl target::ext::breakpoints::SwBreakpoint for Emu {
     }
 }
 
+impl target::ext::breakpoints::HwBreakpoint for Emu {
+    fn add_hw_breakpoint(
+        &mut self,
+        addr: u16,
+        kind: MosBreakpointKind,
+    ) -> TargetResult<bool, Self> {
+        target::ext::breakpoints::SwBreakpoint::add_sw_breakpoint(self, addr, kind)
+    }
+
+    fn remove_hw_breakpoint(
+        &mut self,
+        addr: u16,
+        kind: MosBreakpointKind,
+    ) -> TargetResult<bool, Self> {
+        target::ext::breakpoints::SwBreakpoint::remove_sw_breakpoint(self, addr, kind)
+    }
+}
+
 impl target::ext::breakpoints::HwWatchpoint for Emu {
     fn add_hw_watchpoint(
         &mut self,
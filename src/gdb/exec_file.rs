@@ -0,0 +1,26 @@
+use gdbstub::common::Pid;
+use gdbstub::target;
+use gdbstub::target::{TargetError, TargetResult};
+
+use crate::emu::Emu;
+
+impl target::ext::exec_file::ExecFile for Emu {
+    fn get_exec_file(
+        &self,
+        _pid: Option<Pid>,
+        offset: u64,
+        length: usize,
+        buf: &mut [u8],
+    ) -> TargetResult<usize, Self> {
+        // the path refers to the in-memory filesystem, so the debugger can
+        // fetch the file itself with vFile packets
+        let path = self.exec_file.as_ref().ok_or(TargetError::NonFatal)?;
+        let path = path.as_bytes();
+
+        let start = (offset as usize).min(path.len());
+        let end = (start + length.min(buf.len())).min(path.len());
+        let data = &path[start..end];
+        buf[..data.len()].copy_from_slice(data);
+        Ok(data.len())
+    }
+}
@@ -1,9 +1,13 @@
-use crate::emu::{Emu, InMemoryFile};
+use crate::emu::Emu;
 use gdbstub::target;
 use gdbstub::target::ext::host_io::{
-    FsKind, HostIoErrno, HostIoError, HostIoOpenFlags, HostIoOpenMode, HostIoResult,
+    FsKind, HostIoErrno, HostIoError, HostIoOpenFlags, HostIoOpenMode, HostIoResult, HostIoStat,
 };
 
+fn path_str(filename: &[u8]) -> Result<&str, HostIoError<&'static str>> {
+    std::str::from_utf8(filename).map_err(|_| HostIoError::Errno(HostIoErrno::ENOENT))
+}
+
 impl target::ext::host_io::HostIo for Emu {
     #[inline(always)]
     fn support_open(&mut self) -> Option<target::ext::host_io::HostIoOpenOps<'_, Self>> {
@@ -15,55 +19,122 @@ impl target::ext::host_io::HostIo for Emu {
         Some(self)
     }
 
+    #[inline(always)]
+    fn support_pread(&mut self) -> Option<target::ext::host_io::HostIoPreadOps<'_, Self>> {
+        Some(self)
+    }
+
     #[inline(always)]
     fn support_pwrite(&mut self) -> Option<target::ext::host_io::HostIoPwriteOps<'_, Self>> {
         Some(self)
     }
+
+    #[inline(always)]
+    fn support_fstat(&mut self) -> Option<target::ext::host_io::HostIoFstatOps<'_, Self>> {
+        Some(self)
+    }
+
+    #[inline(always)]
+    fn support_unlink(&mut self) -> Option<target::ext::host_io::HostIoUnlinkOps<'_, Self>> {
+        Some(self)
+    }
+
+    #[inline(always)]
+    fn support_readlink(&mut self) -> Option<target::ext::host_io::HostIoReadlinkOps<'_, Self>> {
+        Some(self)
+    }
+
+    #[inline(always)]
+    fn support_setfs(&mut self) -> Option<target::ext::host_io::HostIoSetfsOps<'_, Self>> {
+        Some(self)
+    }
 }
 
 impl target::ext::host_io::HostIoOpen for Emu {
     fn open(
         &mut self,
         filename: &[u8],
-        _flags: HostIoOpenFlags,
-        _mode: HostIoOpenMode,
+        flags: HostIoOpenFlags,
+        mode: HostIoOpenMode,
     ) -> HostIoResult<u32, Self> {
-        let new_fd = self.files.keys().min().unwrap_or(&0) + 1;
-        let path =
-            std::str::from_utf8(filename).map_err(|_| HostIoError::Errno(HostIoErrno::ENOENT))?;
-
-        let file = InMemoryFile::new(path.to_string());
-        self.files.insert(new_fd, file);
-        Ok(new_fd)
+        let path = path_str(filename)?;
+        self.files
+            .open(path, flags, mode)
+            .map_err(HostIoError::Errno)
     }
 }
 
 impl target::ext::host_io::HostIoClose for Emu {
     fn close(&mut self, fd: u32) -> HostIoResult<(), Self> {
-        let file = self.files.get_mut(&fd);
-        if let Some(file) = file {
-            if &file.data[1..4] == b"ELF" {
-                let data = file.data.clone();
+        let written = self.files.close(fd).map_err(HostIoError::Errno)?;
+        // a freshly uploaded ELF image becomes the program being debugged
+        if let Some((path, data)) = written {
+            if data.starts_with(b"\x7fELF") {
                 self.load_elf(&data)
                     .map_err(|_| HostIoError::Fatal("Can't parse ELF"))?;
+                self.exec_file = Some(path);
             }
         }
         Ok(())
     }
 }
 
+impl target::ext::host_io::HostIoPread for Emu {
+    fn pread(
+        &mut self,
+        fd: u32,
+        count: usize,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> HostIoResult<usize, Self> {
+        let data = self
+            .files
+            .pread(fd, count.min(buf.len()), offset)
+            .map_err(HostIoError::Errno)?;
+        buf[..data.len()].copy_from_slice(data);
+        Ok(data.len())
+    }
+}
+
 impl target::ext::host_io::HostIoPwrite for Emu {
-    fn pwrite(&mut self, fd: u32, _offset: u16, data: &[u8]) -> HostIoResult<u16, Self> {
-        let file = self.files.get_mut(&fd);
-        if let Some(file) = file {
-            file.data.extend(data.iter());
-        }
-        Ok(data.len() as u16)
+    fn pwrite(&mut self, fd: u32, offset: u16, data: &[u8]) -> HostIoResult<u16, Self> {
+        let written = self
+            .files
+            .pwrite(fd, offset, data)
+            .map_err(HostIoError::Errno)?;
+        Ok(written as u16)
+    }
+}
+
+impl target::ext::host_io::HostIoFstat for Emu {
+    fn fstat(&mut self, fd: u32) -> HostIoResult<HostIoStat, Self> {
+        self.files.fstat(fd).map_err(HostIoError::Errno)
+    }
+}
+
+impl target::ext::host_io::HostIoUnlink for Emu {
+    fn unlink(&mut self, filename: &[u8]) -> HostIoResult<(), Self> {
+        let path = path_str(filename)?;
+        self.files.unlink(path).map_err(HostIoError::Errno)
+    }
+}
+
+impl target::ext::host_io::HostIoReadlink for Emu {
+    fn readlink(&mut self, filename: &[u8], _buf: &mut [u8]) -> HostIoResult<usize, Self> {
+        // there are no symlinks in the in-memory filesystem
+        let path = path_str(filename)?;
+        let errno = if self.files.exists(path) {
+            HostIoErrno::EINVAL
+        } else {
+            HostIoErrno::ENOENT
+        };
+        Err(HostIoError::Errno(errno))
     }
 }
 
 impl target::ext::host_io::HostIoSetfs for Emu {
     fn setfs(&mut self, _fs: FsKind) -> HostIoResult<(), Self> {
+        // the stub and the target process share one filesystem
         Ok(())
     }
 }
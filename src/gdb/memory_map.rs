@@ -0,0 +1,22 @@
+use gdbstub::target;
+use gdbstub::target::TargetResult;
+
+use crate::emu::Emu;
+
+impl target::ext::memory_map::MemoryMap for Emu {
+    fn memory_map_xml(
+        &self,
+        offset: u64,
+        length: usize,
+        buf: &mut [u8],
+    ) -> TargetResult<usize, Self> {
+        let xml = self.memory_map.borrow().to_xml();
+        let xml = xml.as_bytes();
+
+        let start = (offset as usize).min(xml.len());
+        let end = (start + length.min(buf.len())).min(xml.len());
+        let data = &xml[start..end];
+        buf[..data.len()].copy_from_slice(data);
+        Ok(data.len())
+    }
+}
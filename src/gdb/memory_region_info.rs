@@ -0,0 +1,222 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use gdbstub::conn::{Connection, ConnectionExt};
+
+use crate::emu::Emu;
+use crate::memory_map::{MemoryMap, RegionKind};
+
+const PACKET: &str = "qMemoryRegionInfo:";
+
+/// Debugger connection answering LLDB's `qMemoryRegionInfo` packets from the
+/// memory map, gdbstub doesn't support them. Everything else is passed on.
+struct MemoryRegionInfo<C> {
+    inner: C,
+    memory_map: Rc<RefCell<MemoryMap>>,
+    /// packet being received, from `$` up to the checksum
+    packet: Vec<u8>,
+    /// bytes for gdbstub
+    pending: VecDeque<u8>,
+    /// set once `QStartNoAckMode` went through, gdbstub stops acking then
+    no_ack: bool,
+}
+
+impl<C: ConnectionExt> MemoryRegionInfo<C> {
+    fn receive(&mut self, byte: u8) -> Result<(), C::Error> {
+        if self.packet.is_empty() && byte != b'$' {
+            // acks and interrupts
+            self.pending.push_back(byte);
+            return Ok(());
+        }
+        self.packet.push(byte);
+        let len = self.packet.len();
+        if len < 4 || self.packet[len - 3] != b'#' {
+            return Ok(());
+        }
+
+        let packet = std::mem::take(&mut self.packet);
+        let body = &packet[1..len - 3];
+        if let Some(addr) = body.strip_prefix(PACKET.as_bytes()) {
+            let addr = std::str::from_utf8(addr)
+                .ok()
+                .and_then(|addr| u64::from_str_radix(addr, 16).ok());
+            if !self.no_ack {
+                self.inner.write(b'+')?;
+            }
+            let reply = match addr {
+                Some(addr) => region_info(&self.memory_map.borrow(), addr),
+                None => "E01".to_string(),
+            };
+            let checksum = reply.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+            self.inner
+                .write_all(format!("${}#{:02x}", reply, checksum).as_bytes())?;
+            return self.inner.flush();
+        }
+        if body == b"QStartNoAckMode" {
+            self.no_ack = true;
+        }
+        self.pending.extend(packet);
+        Ok(())
+    }
+}
+
+/// Reply to `qMemoryRegionInfo` for `addr`. Open bus and addresses past the
+/// 64K space are reported as unmapped, without permissions.
+fn region_info(memory_map: &MemoryMap, addr: u64) -> String {
+    let region = match u16::try_from(addr) {
+        Ok(addr) => memory_map.region_at(addr),
+        Err(_) => return format!("start:10000;size:{:x};", 0u64.wrapping_sub(0x10000)),
+    };
+    let permissions = match region.kind {
+        RegionKind::Ram => "permissions:rwx;",
+        RegionKind::Rom => "permissions:rx;",
+        RegionKind::Io => "permissions:rw;",
+        RegionKind::Unmapped => "",
+    };
+    format!(
+        "start:{:x};size:{:x};{}",
+        region.start, region.len, permissions
+    )
+}
+
+impl<C: ConnectionExt> Connection for MemoryRegionInfo<C> {
+    type Error = C::Error;
+
+    fn write(&mut self, byte: u8) -> Result<(), Self::Error> {
+        self.inner.write(byte)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.inner.write_all(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush()
+    }
+
+    fn on_session_start(&mut self) -> Result<(), Self::Error> {
+        self.inner.on_session_start()
+    }
+}
+
+impl<C: ConnectionExt> ConnectionExt for MemoryRegionInfo<C> {
+    fn read(&mut self) -> Result<u8, Self::Error> {
+        loop {
+            if let Some(byte) = self.pending.pop_front() {
+                return Ok(byte);
+            }
+            let byte = self.inner.read()?;
+            self.receive(byte)?;
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, Self::Error> {
+        loop {
+            if let Some(byte) = self.pending.front() {
+                return Ok(Some(*byte));
+            }
+            if self.inner.peek()?.is_none() {
+                return Ok(None);
+            }
+            let byte = self.inner.read()?;
+            self.receive(byte)?;
+        }
+    }
+}
+
+impl Emu {
+    /// Wraps the connection of a debugger session, so `memory region` works
+    /// in LLDB.
+    pub fn debugger_connection<C: ConnectionExt + 'static>(
+        &self,
+        conn: C,
+    ) -> Box<dyn ConnectionExt<Error = C::Error>> {
+        Box::new(MemoryRegionInfo {
+            inner: conn,
+            memory_map: self.memory_map.clone(),
+            packet: vec![],
+            pending: VecDeque::new(),
+            no_ack: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Connection replaying `input`, collecting what is written
+    #[derive(Default)]
+    struct Mock {
+        input: VecDeque<u8>,
+        output: Vec<u8>,
+    }
+
+    impl Connection for Mock {
+        type Error = ();
+
+        fn write(&mut self, byte: u8) -> Result<(), ()> {
+            self.output.push(byte);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    impl ConnectionExt for Mock {
+        fn read(&mut self) -> Result<u8, ()> {
+            self.input.pop_front().ok_or(())
+        }
+
+        fn peek(&mut self) -> Result<Option<u8>, ()> {
+            Ok(self.input.front().copied())
+        }
+    }
+
+    fn connection(input: &str) -> MemoryRegionInfo<Mock> {
+        MemoryRegionInfo {
+            inner: Mock {
+                input: input.bytes().collect(),
+                output: vec![],
+            },
+            memory_map: Default::default(),
+            packet: vec![],
+            pending: VecDeque::new(),
+            no_ack: false,
+        }
+    }
+
+    fn read_all(conn: &mut MemoryRegionInfo<Mock>) -> String {
+        let mut read = vec![];
+        while let Some(byte) = conn.peek().unwrap() {
+            assert_eq!(conn.read(), Ok(byte));
+            read.push(byte);
+        }
+        String::from_utf8(read).unwrap()
+    }
+
+    #[test]
+    fn answers_region_info() {
+        let mut conn = connection("+$qMemoryRegionInfo:fff4#00$g#67\x03");
+        assert_eq!(read_all(&mut conn), "+$g#67\x03");
+        let reply = "start:fff0;size:a;permissions:rw;";
+        let checksum = reply.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        assert_eq!(
+            String::from_utf8(conn.inner.output).unwrap(),
+            format!("+${}#{:02x}", reply, checksum)
+        );
+    }
+
+    #[test]
+    fn no_ack_mode() {
+        let mut conn = connection("$QStartNoAckMode#b0+$qMemoryRegionInfo:10000#00");
+        assert_eq!(read_all(&mut conn), "$QStartNoAckMode#b0+");
+        assert!(conn
+            .inner
+            .output
+            .starts_with(b"$start:10000;size:ffffffffffff0000;#"));
+    }
+}
@@ -1,20 +1,26 @@
 use gdbstub::common::Signal;
 use gdbstub::target;
+use gdbstub::target::ext::base::single_register_access::SingleRegisterAccess;
 use gdbstub::target::ext::base::singlethread::{SingleThreadBase, SingleThreadResume};
-use gdbstub::target::{Target, TargetResult};
+use gdbstub::target::{Target, TargetError, TargetResult};
 
 use crate::emu::{Emu, ExecMode};
-use gdbstub_mos_arch::{MOSArch, MosRegs};
-
-use emulator_6502::Interface6502;
+use crate::interrupts::Interrupt;
+use arch::{MosArch, MosRegisters};
+use gdbstub_mos_arch::MosRegId;
 
 // Additional GDB extensions
 
+mod arch;
 mod breakpoints;
+mod exec_file;
 mod host_io;
+mod memory_map;
+mod memory_region_info;
+mod monitor_cmd;
 
 impl Target for Emu {
-    type Arch = MOSArch;
+    type Arch = MosArch;
     type Error = &'static str;
 
     // --------------- IMPORTANT NOTE ---------------
@@ -38,25 +44,49 @@ impl Target for Emu {
     fn support_host_io(&mut self) -> Option<target::ext::host_io::HostIoOps<'_, Self>> {
         Some(self)
     }
+
+    #[inline(always)]
+    fn support_monitor_cmd(&mut self) -> Option<target::ext::monitor_cmd::MonitorCmdOps<'_, Self>> {
+        Some(self)
+    }
+
+    #[inline(always)]
+    fn support_memory_map(&mut self) -> Option<target::ext::memory_map::MemoryMapOps<'_, Self>> {
+        Some(self)
+    }
+
+    #[inline(always)]
+    fn support_exec_file(&mut self) -> Option<target::ext::exec_file::ExecFileOps<'_, Self>> {
+        Some(self)
+    }
 }
 
 impl SingleThreadBase for Emu {
-    fn read_registers(&mut self, regs: &mut MosRegs) -> TargetResult<(), Self> {
-        regs.pc = self.cpu.get_program_counter();
-        regs.a = self.cpu.get_accumulator();
-        regs.x = self.cpu.get_x_register();
-        regs.y = self.cpu.get_y_register();
-        regs.s = self.cpu.get_stack_pointer();
-        regs.flags = self.cpu.get_status_register();
-        if let Some(im_reg_map) = &self.im_reg_map {
-            for (idx, addr) in im_reg_map.iter().cloned().enumerate() {
-                regs.rc[idx] = self.system.mem[addr];
+    fn read_registers(&mut self, regs: &mut MosRegisters) -> TargetResult<(), Self> {
+        regs.regs.pc = self.cpu.get_program_counter();
+        regs.regs.a = self.cpu.get_accumulator();
+        regs.regs.x = self.cpu.get_x_register();
+        regs.regs.y = self.cpu.get_y_register();
+        regs.regs.s = self.cpu.get_stack_pointer();
+        regs.regs.flags = self.cpu.get_status_register();
+        // registers without a known location are sent as unavailable
+        for (idx, rc) in regs.regs.rc.iter_mut().enumerate() {
+            if let Some(addr) = self.im_reg_map.get(idx) {
+                *rc = self.system.peek(addr);
+                regs.rc_mapped |= 1 << idx;
+            }
+        }
+        for (idx, rs) in regs.regs.rs.iter_mut().enumerate() {
+            if let Some((lo, hi)) = self.im_reg_map.pair(idx) {
+                *rs = u16::from_le_bytes([self.system.peek(lo), self.system.peek(hi)]);
+                regs.rs_mapped |= 1 << idx;
             }
         }
         Ok(())
     }
 
-    fn write_registers(&mut self, regs: &MosRegs) -> TargetResult<(), Self> {
+    fn write_registers(&mut self, regs: &MosRegisters) -> TargetResult<(), Self> {
+        let regs = &regs.regs;
         self.cpu.set_program_counter(regs.pc);
         self.cpu.set_accumulator(regs.a);
         self.cpu.set_x_register(regs.x);
@@ -64,33 +94,51 @@ impl SingleThreadBase for Emu {
         self.cpu.set_stack_pointer(regs.s);
         self.cpu.set_status_register(regs.flags);
 
-        if let Some(im_reg_map) = &self.im_reg_map {
-            for (idx, addr) in im_reg_map.iter().cloned().enumerate() {
-                self.system.mem[addr] = regs.rc[idx];
+        // RS registers alias pairs of RC registers, only apply the ones
+        // which were changed on their own: compared with memory before the
+        // RC writes, so a stale RS doesn't undo an edited RC
+        let rs_changed: Vec<_> = regs
+            .rs
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, rs)| {
+                let (lo, hi) = self.im_reg_map.pair(idx)?;
+                let current = u16::from_le_bytes([self.system.peek(lo), self.system.peek(hi)]);
+                (*rs != current).then_some((lo, hi, *rs))
+            })
+            .collect();
+        for (idx, rc) in regs.rc.iter().enumerate() {
+            if let Some(addr) = self.im_reg_map.get(idx) {
+                self.system.poke(addr, *rc);
             }
         }
+        for (lo, hi, val) in rs_changed {
+            let [lo_val, hi_val] = val.to_le_bytes();
+            self.system.poke(lo, lo_val);
+            self.system.poke(hi, hi_val);
+        }
 
         Ok(())
     }
 
-    // #[inline(always)]
-    // fn support_single_register_access(
-    //     &mut self,
-    // ) -> Option<target::ext::base::single_register_access::SingleRegisterAccessOps<'_, (), Self>>
-    // {
-    //     Some(self)
-    // }
+    #[inline(always)]
+    fn support_single_register_access(
+        &mut self,
+    ) -> Option<target::ext::base::single_register_access::SingleRegisterAccessOps<'_, (), Self>>
+    {
+        Some(self)
+    }
 
     fn read_addrs(&mut self, start_addr: u16, data: &mut [u8]) -> TargetResult<(), Self> {
         for (addr, val) in (start_addr as usize..).zip(data.iter_mut()) {
-            *val = self.system.read(addr as u16);
+            *val = self.system.peek(addr as u16);
         }
         Ok(())
     }
 
     fn write_addrs(&mut self, start_addr: u16, data: &[u8]) -> TargetResult<(), Self> {
         for (addr, val) in (start_addr as usize..).zip(data.iter().copied()) {
-            self.system.write(addr as u16, val);
+            self.system.poke(addr as u16, val);
         }
         Ok(())
     }
@@ -103,6 +151,99 @@ impl SingleThreadBase for Emu {
     }
 }
 
+/// bit of the status register holding each of the flag pseudo-registers
+fn flag_mask(reg_id: &MosRegId) -> Option<u8> {
+    match reg_id {
+        MosRegId::C => Some(0x01),
+        MosRegId::Z => Some(0x02),
+        MosRegId::V => Some(0x40),
+        MosRegId::N => Some(0x80),
+        _ => None,
+    }
+}
+
+impl SingleRegisterAccess<()> for Emu {
+    fn read_register(
+        &mut self,
+        _tid: (),
+        reg_id: MosRegId,
+        buf: &mut [u8],
+    ) -> TargetResult<usize, Self> {
+        let value: u16 = match reg_id {
+            MosRegId::PC => self.cpu.get_program_counter(),
+            MosRegId::A => self.cpu.get_accumulator() as u16,
+            MosRegId::X => self.cpu.get_x_register() as u16,
+            MosRegId::Y => self.cpu.get_y_register() as u16,
+            MosRegId::S => self.cpu.get_stack_pointer() as u16,
+            MosRegId::C | MosRegId::Z | MosRegId::V | MosRegId::N => {
+                let mask = flag_mask(&reg_id).unwrap();
+                (self.cpu.get_status_register() & mask != 0) as u16
+            }
+            MosRegId::RC(idx) => match self.im_reg_map.get(idx as usize) {
+                Some(addr) => self.system.peek(addr) as u16,
+                // not mapped by the ELF, report as unavailable
+                None => return Ok(0),
+            },
+            MosRegId::RS(idx) => match self.im_reg_map.pair(idx as usize) {
+                Some((lo, hi)) => u16::from_le_bytes([self.system.peek(lo), self.system.peek(hi)]),
+                None => return Ok(0),
+            },
+        };
+        let size = match reg_id {
+            MosRegId::PC | MosRegId::RS(_) => 2,
+            _ => 1,
+        };
+        buf[..size].copy_from_slice(&value.to_le_bytes()[..size]);
+        Ok(size)
+    }
+
+    fn write_register(&mut self, _tid: (), reg_id: MosRegId, val: &[u8]) -> TargetResult<(), Self> {
+        let byte = *val.first().ok_or(TargetError::NonFatal)?;
+        match reg_id {
+            MosRegId::PC | MosRegId::RS(_) if val.len() < 2 => return Err(TargetError::NonFatal),
+            MosRegId::PC => self
+                .cpu
+                .set_program_counter(u16::from_le_bytes([val[0], val[1]])),
+            MosRegId::A => self.cpu.set_accumulator(byte),
+            MosRegId::X => self.cpu.set_x_register(byte),
+            MosRegId::Y => self.cpu.set_y_register(byte),
+            MosRegId::S => self.cpu.set_stack_pointer(byte),
+            MosRegId::C | MosRegId::Z | MosRegId::V | MosRegId::N => {
+                let mask = flag_mask(&reg_id).unwrap();
+                let flags = self.cpu.get_status_register() & !mask;
+                self.cpu
+                    .set_status_register(if byte & 1 != 0 { flags | mask } else { flags });
+            }
+            MosRegId::RC(idx) => {
+                let addr = self.im_reg_map.get(idx as usize);
+                self.system.poke(addr.ok_or(TargetError::NonFatal)?, byte);
+            }
+            MosRegId::RS(idx) => {
+                let (lo, hi) = self
+                    .im_reg_map
+                    .pair(idx as usize)
+                    .ok_or(TargetError::NonFatal)?;
+                self.system.poke(lo, val[0]);
+                self.system.poke(hi, val[1]);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Emu {
+    /// Signals passed on resume (`signal SIGINT` in GDB) raise interrupts:
+    /// SIGINT is NMI, SIGUSR1 is IRQ.
+    fn deliver_signal(&mut self, signal: Option<Signal>) {
+        match signal {
+            None => {}
+            Some(Signal::SIGINT) => self.system.interrupts.request(Interrupt::Nmi),
+            Some(Signal::SIGUSR1) => self.system.interrupts.request(Interrupt::Irq),
+            Some(signal) => log::warn!("ignoring signal {}", signal),
+        }
+    }
+}
+
 impl SingleThreadResume for Emu {
     fn resume(&mut self, signal: Option<Signal>) -> Result<(), Self::Error> {
         // Upon returning from the `resume` method, the target being debugged should be
@@ -118,10 +259,7 @@ impl SingleThreadResume for Emu {
         // external "orchestration" to set it's execution mode (e.g: modifying the
         // target's process state via platform specific debugging syscalls).
 
-        // if signal.is_some() {
-        //     return Err("no support for continuing with signal");
-        // }
-
+        self.deliver_signal(signal);
         self.exec_mode = ExecMode::Continue;
 
         Ok(())
@@ -144,10 +282,7 @@ impl SingleThreadResume for Emu {
 
 impl target::ext::base::singlethread::SingleThreadSingleStep for Emu {
     fn step(&mut self, signal: Option<Signal>) -> Result<(), Self::Error> {
-        if signal.is_some() {
-            return Err("no support for stepping with signal");
-        }
-
+        self.deliver_signal(signal);
         self.exec_mode = ExecMode::Step;
 
         Ok(())
@@ -0,0 +1,269 @@
+use gdbstub::target;
+use gdbstub::target::ext::monitor_cmd::{outputln, ConsoleOutput};
+
+use crate::emu::Emu;
+use crate::interrupts::Interrupt;
+use crate::write_file;
+
+/// IRQ line driven by `monitor irq assert` / `monitor irq release`
+const DEBUGGER_IRQ_LINE: u32 = 31;
+
+const HELP: &str = "\
+irq                 request IRQ
+nmi                 request NMI
+irq at CYCLE        request IRQ once the cycle counter reaches CYCLE
+nmi at CYCLE        request NMI once the cycle counter reaches CYCLE
+irq assert          hold IRQ line low until released
+irq release         release IRQ line
+disas [LOC [COUNT]] disassemble COUNT (default 8) instructions at symbol or
+                    address LOC (default PC)
+history [N]         last N (default all kept) executed instructions
+profile on|off      start / stop the function profiler
+profile reset       drop the profile collected so far
+profile             show cycles and calls per function
+profile callgrind FILE
+                    write the profile in callgrind format to host FILE
+coverage on|off     start / stop counting executions and cycles per source line
+coverage reset      drop the counts collected so far
+coverage lcov FILE  write line coverage in lcov format to host FILE
+coverage annotate FILE
+                    write annotated sources to host FILE
+opcodes on [by-function]
+                    start counting executions and cycles per opcode
+opcodes off         stop counting them
+opcodes reset       drop the counts collected so far
+opcodes             show executions and cycles per opcode and addressing mode
+opcodes json FILE   write them as JSON to host FILE
+samples reset       drop the PC samples taken so far
+samples FILE        write them as a sample profile (AutoFDO) to host FILE
+zp on|off           start / stop counting zero page reads and writes
+zp reset            drop the counts collected so far
+zp                  show traffic per imaginary register and function
+stack on|off        start / stop tracking peak hardware and soft stack depth
+stack check on|off  stop with SIGSEGV on stack overflow or corrupted return
+                    addresses
+stack               show peak stack depths and the call chains reaching them
+timeline on|off     start / stop recording calls, device accesses and interrupts
+timeline FILE       write the timeline as Chrome trace JSON to host FILE
+trace on|off        start / stop logging executed instructions
+trace filter [SPEC] only trace functions and address ranges in SPEC, e.g.
+                    `main,0x1000-0x10ff`; no SPEC traces everything";
+
+impl target::ext::monitor_cmd::MonitorCmd for Emu {
+    fn handle_monitor_cmd(
+        &mut self,
+        cmd: &[u8],
+        mut out: ConsoleOutput<'_>,
+    ) -> Result<(), Self::Error> {
+        let cmd = match std::str::from_utf8(cmd) {
+            Ok(cmd) => cmd,
+            Err(_) => {
+                outputln!(out, "command must be valid UTF-8");
+                return Ok(());
+            }
+        };
+        let words: Vec<&str> = cmd.split_whitespace().collect();
+
+        match words.as_slice() {
+            [kind @ ("irq" | "nmi"), rest @ ..] => {
+                let interrupt = if *kind == "irq" {
+                    Interrupt::Irq
+                } else {
+                    Interrupt::Nmi
+                };
+                match rest {
+                    [] => {
+                        self.system.interrupts.request(interrupt);
+                        outputln!(out, "{:?} requested", interrupt);
+                    }
+                    ["assert"] if interrupt == Interrupt::Irq => {
+                        self.system.interrupts.set_irq_line(DEBUGGER_IRQ_LINE, true);
+                        outputln!(out, "IRQ line asserted");
+                    }
+                    ["release"] if interrupt == Interrupt::Irq => {
+                        self.system
+                            .interrupts
+                            .set_irq_line(DEBUGGER_IRQ_LINE, false);
+                        outputln!(out, "IRQ line released");
+                    }
+                    ["at", cycle] => match cycle.parse::<u64>() {
+                        Ok(cycle) => {
+                            self.schedule_interrupt(cycle, interrupt);
+                            outputln!(out, "{:?} scheduled at cycle {}", interrupt, cycle);
+                        }
+                        Err(_) => outputln!(out, "invalid cycle: {}", cycle),
+                    },
+                    _ => outputln!(out, "{}", HELP),
+                }
+            }
+            ["disas", rest @ ..] if rest.len() <= 2 => {
+                let addr = match rest.first() {
+                    Some(location) => match self.resolve(location) {
+                        Ok(addr) => addr,
+                        Err(err) => {
+                            outputln!(out, "{}", err);
+                            return Ok(());
+                        }
+                    },
+                    None => self.cpu.get_program_counter(),
+                };
+                let count = match rest.get(1).map(|n| n.parse::<usize>()) {
+                    Some(Ok(count)) => count,
+                    Some(Err(_)) => {
+                        outputln!(out, "invalid count: {}", rest[1]);
+                        return Ok(());
+                    }
+                    None => 8,
+                };
+                for line in self.disassemble(addr, count) {
+                    outputln!(out, "{}", line);
+                }
+            }
+            ["history", rest @ ..] if rest.len() <= 1 => {
+                let count = match rest.first().map(|n| n.parse::<usize>()) {
+                    Some(Ok(count)) => count,
+                    Some(Err(_)) => {
+                        outputln!(out, "invalid count: {}", rest[0]);
+                        return Ok(());
+                    }
+                    None => usize::MAX,
+                };
+                for line in self.history(count) {
+                    outputln!(out, "{}", line);
+                }
+            }
+            ["profile"] => outputln!(out, "{}", self.profile_report()),
+            ["profile", "on"] => {
+                self.set_profiling(true);
+                outputln!(out, "profiling on");
+            }
+            ["profile", "off"] => {
+                self.set_profiling(false);
+                outputln!(out, "profiling off");
+            }
+            ["profile", "reset"] => {
+                self.reset_profile();
+                outputln!(out, "profile reset");
+            }
+            ["profile", "callgrind", path] => {
+                match write_file(path, |file| self.write_callgrind(file)) {
+                    Ok(()) => outputln!(out, "profile written to {}", path),
+                    Err(err) => outputln!(out, "{}: {}", path, err),
+                }
+            }
+            ["coverage", "on"] => {
+                self.set_coverage(true);
+                outputln!(out, "coverage on");
+            }
+            ["coverage", "off"] => {
+                self.set_coverage(false);
+                outputln!(out, "coverage off");
+            }
+            ["coverage", "reset"] => {
+                self.reset_coverage();
+                outputln!(out, "coverage reset");
+            }
+            ["coverage", kind @ ("lcov" | "annotate"), path] => {
+                let res = if *kind == "lcov" {
+                    write_file(path, |file| self.write_lcov(file))
+                } else {
+                    write_file(path, |file| self.write_annotated_source(file))
+                };
+                match res {
+                    Ok(()) => outputln!(out, "coverage written to {}", path),
+                    Err(err) => outputln!(out, "{}: {}", path, err),
+                }
+            }
+            ["opcodes"] => outputln!(out, "{}", self.opcode_report()),
+            ["opcodes", "on", rest @ ..] if matches!(rest, [] | ["by-function"]) => {
+                self.set_opcode_stats(true, !rest.is_empty());
+                outputln!(out, "opcode stats on");
+            }
+            ["opcodes", "off"] => {
+                self.set_opcode_stats(false, false);
+                outputln!(out, "opcode stats off");
+            }
+            ["opcodes", "reset"] => {
+                self.reset_opcode_stats();
+                outputln!(out, "opcode stats reset");
+            }
+            ["opcodes", "json", path] => {
+                match write_file(path, |file| self.write_opcode_json(file)) {
+                    Ok(()) => outputln!(out, "opcode stats written to {}", path),
+                    Err(err) => outputln!(out, "{}: {}", path, err),
+                }
+            }
+            ["samples", "reset"] => {
+                self.reset_samples();
+                outputln!(out, "samples reset");
+            }
+            ["samples", path] => match write_file(path, |file| self.write_sample_profile(file)) {
+                Ok(()) => outputln!(out, "sample profile written to {}", path),
+                Err(err) => outputln!(out, "{}: {}", path, err),
+            },
+            ["zp"] => outputln!(out, "{}", self.zp_report()),
+            ["zp", "on"] => {
+                self.set_zp_stats(true);
+                outputln!(out, "zero page stats on");
+            }
+            ["zp", "off"] => {
+                self.set_zp_stats(false);
+                outputln!(out, "zero page stats off");
+            }
+            ["zp", "reset"] => {
+                self.reset_zp_stats();
+                outputln!(out, "zero page stats reset");
+            }
+            ["stack"] => outputln!(out, "{}", self.stack_usage_report()),
+            ["stack", "on"] => {
+                self.set_stack_usage(true);
+                outputln!(out, "stack usage on");
+            }
+            ["stack", "off"] => {
+                self.set_stack_usage(false);
+                outputln!(out, "stack usage off");
+            }
+            ["stack", "check", "on"] => {
+                self.set_stack_check(true);
+                outputln!(out, "stack check on");
+            }
+            ["stack", "check", "off"] => {
+                self.set_stack_check(false);
+                outputln!(out, "stack check off");
+            }
+            ["timeline", "on"] => {
+                self.set_timeline(true);
+                outputln!(out, "timeline on");
+            }
+            ["timeline", "off"] => {
+                self.set_timeline(false);
+                outputln!(out, "timeline off");
+            }
+            ["timeline", path] => match write_file(path, |file| self.write_timeline(file)) {
+                Ok(()) => outputln!(out, "timeline written to {}", path),
+                Err(err) => outputln!(out, "{}: {}", path, err),
+            },
+            ["trace", "on"] => {
+                self.trace.set_enabled(true);
+                outputln!(out, "trace on");
+            }
+            ["trace", "off"] => {
+                self.trace.set_enabled(false);
+                outputln!(out, "trace off");
+            }
+            ["trace", "filter", spec @ ..] if spec.len() <= 1 => {
+                match self.set_trace_filter(spec.first().unwrap_or(&"")) {
+                    Ok(unknown) => {
+                        for name in unknown {
+                            outputln!(out, "no function {}", name);
+                        }
+                    }
+                    Err(err) => outputln!(out, "{}", err),
+                }
+            }
+            _ => outputln!(out, "{}", HELP),
+        }
+
+        Ok(())
+    }
+}
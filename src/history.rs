@@ -0,0 +1,49 @@
+use std::collections::VecDeque;
+
+use crate::emu::Registers;
+
+/// Default number of instructions kept
+pub const DEFAULT_HISTORY_LEN: usize = 64;
+
+/// Register state at the start of the most recent instructions, cheap
+/// enough to keep for every instruction of long runs.
+#[derive(Debug)]
+pub struct History {
+    entries: VecDeque<(u64, Registers)>,
+    capacity: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_LEN)
+    }
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Records instruction about to run at `cycle`.
+    pub fn push(&mut self, cycle: u64, regs: Registers) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((cycle, regs));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// oldest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &(u64, Registers)> + '_ {
+        self.entries.iter()
+    }
+}
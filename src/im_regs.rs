@@ -0,0 +1,177 @@
+use goblin::elf::Elf;
+
+/// Number of `__rcN` registers the debugger knows about
+const MAX_REGS: usize = 32;
+
+/// Zero page locations of the llvm-mos imaginary registers.
+///
+/// Index `n` holds the address of `__rcN`; the 16-bit register `__rsN` is the
+/// pair `__rc(2N)`, `__rc(2N+1)`.
+#[derive(Debug, Default, Clone)]
+pub struct ImRegMap {
+    regs: Vec<Option<u16>>,
+}
+
+impl ImRegMap {
+    /// Collects `__rcN` and `__rsN` symbols of `elf`. Registers don't have to
+    /// be contiguous.
+    pub fn from_elf(elf: &Elf<'_>) -> Self {
+        let mut map = Self::default();
+        for sym in elf.syms.iter() {
+            let sym_name = elf.strtab.get_at(sym.st_name).unwrap_or("");
+            let (idx, width) = if let Some(idx) = sym_name.strip_prefix("__rc") {
+                (idx, 1)
+            } else if let Some(idx) = sym_name.strip_prefix("__rs") {
+                (idx, 2)
+            } else {
+                continue;
+            };
+            let idx = match idx.parse::<usize>() {
+                Ok(idx) if idx * width < MAX_REGS => idx * width,
+                _ => continue,
+            };
+            if sym.st_value + width as u64 > 256 {
+                log::warn!(
+                    "invalid imaginary reg mapping: {} -> {:04x?}",
+                    sym_name,
+                    sym.st_value
+                );
+                continue;
+            }
+            log::info!(
+                "imaginary reg mapping: {} -> {:02x?}",
+                sym_name,
+                sym.st_value
+            );
+            for i in 0..width {
+                map.set_if_missing(idx + i, sym.st_value as u16 + i as u16);
+            }
+        }
+        map
+    }
+
+    /// Parses a command line override: comma separated list of zero page
+    /// addresses, each optionally followed by `:count` for a run of
+    /// consecutive registers, e.g. `0x02:16,0x40:16`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut map = Self::default();
+        let mut idx = 0;
+        for item in spec.split(',') {
+            let (addr, count) = match item.split_once(':') {
+                Some((addr, count)) => (addr, parse_num(count)?),
+                None => (item, 1),
+            };
+            let addr = parse_num(addr)?;
+            if addr > 256 || count > 256 - addr {
+                return Err(format!("imaginary registers outside zero page: {}", item));
+            }
+            if idx + count as usize > MAX_REGS {
+                return Err(format!("more than {} imaginary registers", MAX_REGS));
+            }
+            for i in 0..count {
+                map.set_if_missing(idx, (addr + i) as u16);
+                idx += 1;
+            }
+        }
+        Ok(map)
+    }
+
+    /// Fills registers missing from `self` with the ones found in `other`.
+    pub fn merge(&mut self, other: &ImRegMap) {
+        for (idx, addr) in other.iter() {
+            self.set_if_missing(idx, addr);
+        }
+    }
+
+    fn set_if_missing(&mut self, idx: usize, addr: u16) {
+        if self.regs.len() <= idx {
+            self.regs.resize(idx + 1, None);
+        }
+        self.regs[idx].get_or_insert(addr);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regs.iter().all(Option::is_none)
+    }
+
+    /// address of `__rcN`
+    pub fn get(&self, idx: usize) -> Option<u16> {
+        self.regs.get(idx).copied().flatten()
+    }
+
+    /// addresses of the low and high byte of `__rsN`
+    pub fn pair(&self, idx: usize) -> Option<(u16, u16)> {
+        Some((self.get(idx * 2)?, self.get(idx * 2 + 1)?))
+    }
+
+    /// index of the register at zero page `addr`
+    pub fn index_of(&self, addr: u16) -> Option<usize> {
+        self.regs.iter().position(|reg| *reg == Some(addr))
+    }
+
+    /// all known registers as `(index, address)`
+    pub fn iter(&self) -> impl Iterator<Item = (usize, u16)> + '_ {
+        self.regs
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, addr)| Some((idx, (*addr)?)))
+    }
+}
+
+/// Parses a decimal or `0x` / `$` prefixed hexadecimal number.
+pub(crate) fn parse_num(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    let res = match s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    res.map_err(|_| format!("invalid number: {:?}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_runs() {
+        let map = ImRegMap::parse("0x02:4, $10,32:2").unwrap();
+        let regs: Vec<_> = map.iter().collect();
+        assert_eq!(
+            regs,
+            [
+                (0, 0x02),
+                (1, 0x03),
+                (2, 0x04),
+                (3, 0x05),
+                (4, 0x10),
+                (5, 32),
+                (6, 33)
+            ]
+        );
+        assert_eq!(map.pair(0), Some((0x02, 0x03)));
+        assert_eq!(map.pair(3), None);
+        assert_eq!(map.get(7), None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(ImRegMap::parse("0x02:32").is_ok());
+        assert!(ImRegMap::parse("0x02:33").is_err());
+        assert!(ImRegMap::parse("0x02:16,0x40:17").is_err());
+        assert!(ImRegMap::parse("0xff:2").is_err());
+        assert!(ImRegMap::parse("0x101").is_err());
+        assert!(ImRegMap::parse("0x80:4294967295").is_err());
+        assert!(ImRegMap::parse("4294967295:2").is_err());
+        assert!(ImRegMap::parse("0x02:").is_err());
+        assert!(ImRegMap::parse("rc2").is_err());
+        assert!(ImRegMap::parse("").is_err());
+    }
+
+    #[test]
+    fn merge_keeps_existing() {
+        let mut map = ImRegMap::parse("0x02:2").unwrap();
+        map.merge(&ImRegMap::parse("0x80:4").unwrap());
+        let regs: Vec<_> = map.iter().collect();
+        assert_eq!(regs, [(0, 0x02), (1, 0x03), (2, 0x82), (3, 0x83)]);
+    }
+}
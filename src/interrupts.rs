@@ -0,0 +1,63 @@
+pub const NMI_VECTOR: u16 = 0xfffa;
+pub const IRQ_VECTOR: u16 = 0xfffe;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interrupt {
+    Irq,
+    Nmi,
+}
+
+impl Interrupt {
+    pub fn vector(self) -> u16 {
+        match self {
+            Interrupt::Irq => IRQ_VECTOR,
+            Interrupt::Nmi => NMI_VECTOR,
+        }
+    }
+}
+
+/// State of the CPU interrupt inputs.
+///
+/// IRQ is level triggered: devices hold their line (one bit of `irq_lines`
+/// each) until the handler acknowledges them. Requests coming from the
+/// debugger or a schedule are one-shot and dropped once the CPU takes them,
+/// as is NMI, which is edge triggered.
+#[derive(Debug, Default)]
+pub struct InterruptLines {
+    irq_lines: u32,
+    irq_pending: bool,
+    nmi_pending: bool,
+}
+
+impl InterruptLines {
+    /// Drives IRQ line of device `source` (0..32).
+    pub fn set_irq_line(&mut self, source: u32, level: bool) {
+        if level {
+            self.irq_lines |= 1 << source;
+        } else {
+            self.irq_lines &= !(1 << source);
+        }
+    }
+
+    /// One-shot interrupt request.
+    pub fn request(&mut self, interrupt: Interrupt) {
+        match interrupt {
+            Interrupt::Irq => self.irq_pending = true,
+            Interrupt::Nmi => self.nmi_pending = true,
+        }
+    }
+
+    /// Returns interrupt to be taken by the CPU at instruction boundary,
+    /// given the state of the `I` flag. Clears one-shot requests.
+    pub fn take(&mut self, irq_disabled: bool) -> Option<Interrupt> {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            Some(Interrupt::Nmi)
+        } else if !irq_disabled && (self.irq_pending || self.irq_lines != 0) {
+            self.irq_pending = false;
+            Some(Interrupt::Irq)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! 6502 simulator for llvm-mos programs, with a GDB remote target.
+//!
+//! The `sim6502` binary wraps `Emu` in a GDB stub; the library lets test
+//! harnesses drive it directly:
+//!
+//! ```no_run
+//! use sim6502::{Emu, Machine, StopReason};
+//!
+//! let mut emu = Emu::new(Machine::default())?;
+//! emu.load_elf(&std::fs::read("hello.elf")?)?;
+//! assert_eq!(emu.run_for(1_000_000), StopReason::Exited(0));
+//! assert_eq!(emu.output(), b"HELLO, WORLD!\n");
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+#![deny(rust_2018_idioms, future_incompatible, nonstandard_style)]
+
+pub type DynResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+/// Creates host file `path` and fills it with `write`.
+pub(crate) fn write_file<E: From<std::io::Error>>(
+    path: &str,
+    write: impl FnOnce(&mut dyn std::io::Write) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    write(&mut file)?;
+    Ok(std::io::Write::flush(&mut file)?)
+}
+
+mod autofdo;
+mod call;
+mod compare;
+mod coverage;
+mod device;
+pub mod disasm;
+mod emu;
+mod gdb;
+mod history;
+mod im_regs;
+mod interrupts;
+mod lines;
+mod machine;
+mod memory_map;
+mod opcode_stats;
+mod profile;
+mod profraw;
+mod stack_check;
+mod stack_usage;
+mod symbols;
+mod timeline;
+mod timer;
+mod trace;
+mod vfs;
+mod zp_stats;
+
+pub use call::{Arg, Return};
+pub use compare::{FunctionProfile, RunProfile};
+pub use coverage::LineStats;
+pub use device::Device;
+pub use emu::{Emu, Event, Registers, RunEvent, StopReason};
+pub use im_regs::ImRegMap;
+pub use interrupts::Interrupt;
+pub use machine::{CpuModel, Devices, Machine, MemRegion, RomRegion};
+pub use stack_check::StackFault;
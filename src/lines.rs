@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use gimli::{EndianSlice, LittleEndian};
+use goblin::elf::Elf;
+
+/// Marks addresses without line information in `LineTable::addr_locs`.
+const NO_LOC: u32 = u32::MAX;
+
+/// Source location: index into `LineTable::files` and line number.
+pub type Loc = (u32, u32);
+
+pub(crate) type Dwarf<'a> = gimli::Dwarf<EndianSlice<'a, LittleEndian>>;
+
+/// DWARF sections of `elf`, missing ones are empty.
+pub(crate) fn load_dwarf<'a>(elf: &Elf<'_>, data: &'a [u8]) -> Result<Dwarf<'a>, gimli::Error> {
+    gimli::Dwarf::load(|id| -> Result<_, gimli::Error> {
+        let header = elf
+            .section_headers
+            .iter()
+            .find(|h| elf.shdr_strtab.get_at(h.sh_name) == Some(id.name()));
+        let data = header
+            .and_then(|h| h.file_range())
+            .and_then(|range| data.get(range))
+            .unwrap_or(&[]);
+        Ok(EndianSlice::new(data, LittleEndian))
+    })
+}
+
+/// Address to source line mapping from the DWARF `.debug_line` program.
+#[derive(Debug, Clone)]
+pub struct LineTable {
+    /// full paths of source files
+    pub files: Vec<String>,
+    /// all locations with code, each line once
+    pub locs: Vec<Loc>,
+    /// index into `locs` for every address
+    addr_locs: Vec<u32>,
+}
+
+impl Default for LineTable {
+    fn default() -> Self {
+        Self {
+            files: vec![],
+            locs: vec![],
+            addr_locs: vec![NO_LOC; 0x10000],
+        }
+    }
+}
+
+impl LineTable {
+    pub fn from_elf(elf: &Elf<'_>, data: &[u8]) -> Result<Self, gimli::Error> {
+        let dwarf = load_dwarf(elf, data)?;
+
+        let mut table = Self::default();
+        let mut file_ids = HashMap::new();
+        let mut loc_ids = HashMap::new();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let program = match unit.line_program.clone() {
+                Some(program) => program,
+                None => continue,
+            };
+            let comp_dir = unit.comp_dir.map(|dir| dir.to_string_lossy().into_owned());
+
+            // row where the current address range started
+            let mut range_start: Option<(u64, u32)> = None;
+            let mut rows = program.rows();
+            while let Some((header, row)) = rows.next_row()? {
+                if let Some((start, loc)) = range_start.take() {
+                    let end = row.address().min(0x10000);
+                    for addr in start..end {
+                        table.addr_locs[addr as usize] = loc;
+                    }
+                }
+                if row.end_sequence() {
+                    continue;
+                }
+                let (file, line) = match (row.file(header), row.line()) {
+                    (Some(file), Some(line)) => (file, line.get() as u32),
+                    _ => {
+                        range_start = Some((row.address(), NO_LOC));
+                        continue;
+                    }
+                };
+
+                let mut path = PathBuf::new();
+                if let Some(dir) = &comp_dir {
+                    path.push(dir);
+                }
+                if let Some(dir) = file.directory(header) {
+                    path.push(dwarf.attr_string(&unit, dir)?.to_string_lossy().as_ref());
+                }
+                path.push(
+                    dwarf
+                        .attr_string(&unit, file.path_name())?
+                        .to_string_lossy()
+                        .as_ref(),
+                );
+                let path = path.to_string_lossy().into_owned();
+
+                let file_id = *file_ids.entry(path.clone()).or_insert_with(|| {
+                    table.files.push(path);
+                    table.files.len() as u32 - 1
+                });
+                let loc_id = *loc_ids.entry((file_id, line)).or_insert_with(|| {
+                    table.locs.push((file_id, line));
+                    table.locs.len() as u32 - 1
+                });
+                range_start = Some((row.address(), loc_id));
+            }
+        }
+        Ok(table)
+    }
+
+    /// index into `locs` of the line `addr` belongs to
+    pub fn loc_id(&self, addr: u16) -> Option<usize> {
+        match self.addr_locs[addr as usize] {
+            NO_LOC => None,
+            id => Some(id as usize),
+        }
+    }
+
+    /// `file:line` of `addr`
+    pub fn location(&self, addr: u16) -> Option<String> {
+        let (file, line) = self.locs[self.loc_id(addr)?];
+        Some(format!("{}:{}", self.files[file as usize], line))
+    }
+}
@@ -0,0 +1,157 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::DynResult;
+
+/// CPU models understood by the emulation core
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub enum CpuModel {
+    #[default]
+    #[serde(rename = "6502")]
+    Nmos6502,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemRegion {
+    pub start: u16,
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RomRegion {
+    pub start: u16,
+    pub size: u32,
+    /// raw image loaded at `start`, relative to the machine file
+    pub image: Option<String>,
+    #[serde(skip)]
+    pub data: Vec<u8>,
+}
+
+/// Base addresses of the built-in devices, a missing entry means the device
+/// is not present.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Devices {
+    /// cycle counter, 4 ports
+    pub clock: Option<u16>,
+    /// programmable timer, 4 ports
+    pub timer: Option<u16>,
+    /// exit port, written value is the exit status
+    pub exit: Option<u16>,
+    /// character output
+    pub console: Option<u16>,
+    /// instruction trace on (non-zero write) / off (zero write), not mapped
+    /// by default: the `sim` target has no free port left for it
+    pub trace: Option<u16>,
+}
+
+impl Default for Devices {
+    /// layout of the llvm-mos `sim` target
+    fn default() -> Self {
+        Self {
+            clock: Some(0xfff0),
+            timer: Some(0xfff4),
+            exit: Some(0xfff8),
+            console: Some(0xfff9),
+            trace: None,
+        }
+    }
+}
+
+/// Machine description: CPU, memory regions and device placement.
+///
+/// Addresses not covered by any region or device are open bus. Without a
+/// machine file the whole address space is RAM, with devices at the
+/// llvm-mos `sim` target ports.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Machine {
+    #[serde(default)]
+    pub cpu: CpuModel,
+    #[serde(default)]
+    pub ram: Vec<MemRegion>,
+    #[serde(default)]
+    pub rom: Vec<RomRegion>,
+    #[serde(default)]
+    pub open_bus: Vec<MemRegion>,
+    #[serde(default)]
+    pub devices: Devices,
+    /// CPU clock, to convert cycle counts to time
+    #[serde(default = "default_clock_hz")]
+    pub clock_hz: u32,
+}
+
+fn default_clock_hz() -> u32 {
+    1_000_000
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self {
+            cpu: CpuModel::default(),
+            ram: vec![MemRegion {
+                start: 0,
+                size: 0x10000,
+            }],
+            rom: vec![],
+            open_bus: vec![],
+            devices: Devices::default(),
+            clock_hz: default_clock_hz(),
+        }
+    }
+}
+
+impl Machine {
+    /// Checks that regions lie within the address space, ROM contents fit
+    /// their regions and the clock runs.
+    pub fn validate(&self) -> DynResult<()> {
+        if self.clock_hz == 0 {
+            return Err("clock_hz must not be 0".into());
+        }
+        let regions = self
+            .ram
+            .iter()
+            .chain(&self.open_bus)
+            .map(|r| (r.start, r.size));
+        let roms = self.rom.iter().map(|r| (r.start, r.size));
+        for (start, size) in regions.chain(roms) {
+            if start as u32 + size > 0x10000 {
+                return Err(
+                    format!("region {:#06x}+{:#x} exceeds address space", start, size).into(),
+                );
+            }
+        }
+        for rom in &self.rom {
+            if rom.data.len() > rom.size as usize {
+                return Err(format!(
+                    "ROM image {} ({} bytes) doesn't fit in {:#x} bytes at {:#06x}",
+                    rom.image.as_deref().unwrap_or("data"),
+                    rom.data.len(),
+                    rom.size,
+                    rom.start
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads machine description (TOML) from `path`, together with the ROM
+    /// images it refers to.
+    pub fn load(path: &str) -> DynResult<Self> {
+        let mut machine: Machine = toml::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let base_dir = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+
+        for rom in machine.rom.iter_mut() {
+            if let Some(image) = &rom.image {
+                rom.data = std::fs::read(base_dir.join(image))?;
+            }
+        }
+        machine.validate().map_err(|e| format!("{}: {}", path, e))?;
+        Ok(machine)
+    }
+}
@@ -11,10 +11,9 @@ use gdbstub::stub::SingleThreadStopReason;
 use gdbstub::stub::{run_blocking, DisconnectReason, GdbStub, GdbStubError};
 use gdbstub::target::Target;
 
-pub type DynResult<T> = Result<T, Box<dyn std::error::Error>>;
+use sim6502::{DynResult, Emu, Event, ImRegMap, Machine, RunEvent, RunProfile, StopReason};
 
-mod emu;
-mod gdb;
+mod args;
 
 fn wait_for_tcp(port: u16) -> DynResult<TcpStream> {
     let sockaddr = format!("0.0.0.0:{}", port);
@@ -49,13 +48,13 @@ fn wait_for_uds(path: &str) -> DynResult<UnixStream> {
 enum EmuGdbEventLoop {}
 
 impl run_blocking::BlockingEventLoop for EmuGdbEventLoop {
-    type Target = emu::Emu;
+    type Target = Emu;
     type Connection = Box<dyn ConnectionExt<Error = std::io::Error>>;
     type StopReason = SingleThreadStopReason<u16>;
 
     #[allow(clippy::type_complexity)]
     fn wait_for_stop_reason(
-        target: &mut emu::Emu,
+        target: &mut Emu,
         conn: &mut Self::Connection,
     ) -> Result<
         run_blocking::Event<SingleThreadStopReason<u16>>,
@@ -94,30 +93,35 @@ impl run_blocking::BlockingEventLoop for EmuGdbEventLoop {
         };
 
         match target.run(poll_incoming_data) {
-            emu::RunEvent::IncomingData => {
+            RunEvent::IncomingData => {
                 let byte = conn
                     .read()
                     .map_err(run_blocking::WaitForStopReasonError::Connection)?;
                 Ok(run_blocking::Event::IncomingData(byte))
             }
-            emu::RunEvent::Event(event) => {
+            RunEvent::Event(event) => {
                 use gdbstub::target::ext::breakpoints::WatchKind;
 
                 // translate emulator stop reason into GDB stop reason
                 let stop_reason = match event {
-                    emu::Event::DoneStep => SingleThreadStopReason::DoneStep,
-                    emu::Event::Halted => SingleThreadStopReason::Terminated(Signal::SIGSTOP),
-                    emu::Event::Break => SingleThreadStopReason::SwBreak(()),
-                    emu::Event::WatchWrite(addr) => SingleThreadStopReason::Watch {
+                    Event::DoneStep => SingleThreadStopReason::DoneStep,
+                    // reported as an exit, so the session ends through
+                    // `DisconnectReason::TargetExited` and the reports get written
+                    Event::Halted => {
+                        SingleThreadStopReason::Exited(target.exit_code().unwrap_or(0))
+                    }
+                    Event::Break => SingleThreadStopReason::SwBreak(()),
+                    Event::WatchWrite(addr) => SingleThreadStopReason::Watch {
                         tid: (),
                         kind: WatchKind::Write,
                         addr: addr as u16,
                     },
-                    emu::Event::WatchRead(addr) => SingleThreadStopReason::Watch {
+                    Event::WatchRead(addr) => SingleThreadStopReason::Watch {
                         tid: (),
                         kind: WatchKind::Read,
                         addr: addr as u16,
                     },
+                    Event::StackFault(_) => SingleThreadStopReason::Signal(Signal::SIGSEGV),
                 };
 
                 Ok(run_blocking::Event::TargetStopped(stop_reason))
@@ -126,8 +130,8 @@ impl run_blocking::BlockingEventLoop for EmuGdbEventLoop {
     }
 
     fn on_interrupt(
-        _target: &mut emu::Emu,
-    ) -> Result<Option<SingleThreadStopReason<u16>>, <emu::Emu as Target>::Error> {
+        _target: &mut Emu,
+    ) -> Result<Option<SingleThreadStopReason<u16>>, <Emu as Target>::Error> {
         // Because this emulator runs as part of the GDB stub loop, there isn't any
         // special action that needs to be taken to interrupt the underlying target. It
         // is implicitly paused whenever the stub isn't within the
@@ -136,13 +140,179 @@ impl run_blocking::BlockingEventLoop for EmuGdbEventLoop {
     }
 }
 
+fn write_file<E: From<std::io::Error>>(
+    path: &str,
+    write: impl FnOnce(&mut dyn std::io::Write) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    write(&mut file)?;
+    Ok(std::io::Write::flush(&mut file)?)
+}
+
+/// Reports function profile, line coverage, opcode and zero page stats,
+/// stack usage and timeline as requested on the command line, and llvm
+/// instrumentation counters of instrumented programs.
+fn write_profile(emu: &Emu, args: &args::Args) -> DynResult<()> {
+    if args.profile {
+        eprint!("{}", emu.profile_report());
+    }
+    if let Some(path) = &args.callgrind {
+        write_file(path, |file| emu.write_callgrind(file))?;
+    }
+    if let Some(path) = &args.lcov {
+        write_file(path, |file| emu.write_lcov(file))?;
+    }
+    if let Some(path) = &args.annotate {
+        write_file(path, |file| emu.write_annotated_source(file))?;
+    }
+    if args.opcodes {
+        eprint!("{}", emu.opcode_report());
+    }
+    if let Some(path) = &args.opcodes_json {
+        write_file(path, |file| emu.write_opcode_json(file))?;
+    }
+    if args.zp_stats {
+        eprint!("{}", emu.zp_report());
+    }
+    if args.stack_usage {
+        eprint!("{}", emu.stack_usage_report());
+    }
+    if let Some(path) = &args.timeline {
+        write_file(path, |file| emu.write_timeline(file))?;
+    }
+    if let Some(path) = &args.sample_profile {
+        write_file(path, |file| emu.write_sample_profile(file))?;
+    }
+    if emu.is_instrumented() {
+        let path = args
+            .profraw
+            .clone()
+            .or_else(|| std::env::var("LLVM_PROFILE_FILE").ok())
+            .unwrap_or_else(|| "default.profraw".into());
+        write_file(&path, |file| emu.write_profraw(file))?;
+    }
+    Ok(())
+}
+
+/// Runs build `path` of the program headless with the profiler on, for
+/// `compare`.
+fn profile_build(args: &args::Args, path: &str) -> DynResult<RunProfile> {
+    let mut emu = Emu::default();
+    if let Some(path) = &args.machine {
+        emu.set_machine(Machine::load(path)?)?;
+    }
+    if let Some(spec) = &args.im_regs {
+        emu.set_im_regs(ImRegMap::parse(spec)?);
+    }
+    emu.set_profiling(true);
+    for (cycle, interrupt) in args.interrupts.iter().copied() {
+        emu.schedule_interrupt(cycle, interrupt);
+    }
+    emu.load_elf_file(path)?;
+    if emu.run_profile().functions.is_empty() {
+        return Err(format!("{}: no function symbols to compare", path).into());
+    }
+    if let StopReason::CycleLimit = emu.run_for(args.max_cycles.unwrap_or(u64::MAX)) {
+        eprintln!("{}: stopped after {} cycles", path, emu.cycles());
+    }
+    Ok(emu.run_profile())
+}
+
 fn main() -> DynResult<()> {
     pretty_env_logger::init();
 
-    let mut emu = emu::Emu::default();
+    let args = args::Args::parse();
+
+    if let args::Command::Compare { candidate } = &args.command {
+        let base = profile_build(&args, args.elf.as_ref().unwrap())?;
+        let cand = profile_build(&args, candidate)?;
+        print!("{}", base.compare(&cand));
+        return Ok(());
+    }
+
+    let mut emu = Emu::default();
+    emu.set_console_echo(true);
+    if let Some(path) = &args.machine {
+        emu.set_machine(Machine::load(path)?)?;
+    }
+    if let Some(path) = &args.symbols {
+        emu.set_symbol_file(std::fs::read(path)?);
+    }
+    if let Some(spec) = &args.im_regs {
+        emu.set_im_regs(ImRegMap::parse(spec)?);
+    }
+    if let Some(path) = &args.trace_file {
+        let file = std::fs::File::create(path)?;
+        emu.set_trace_output(Box::new(std::io::BufWriter::new(file)));
+    }
+    if let Some(spec) = &args.trace_filter {
+        emu.set_trace_filter(spec)?;
+    }
+    emu.set_trace(args.trace);
+    if let Some(len) = args.history {
+        emu.set_history_len(len);
+    }
+    emu.set_report_history(true);
+    emu.set_profiling(args.profile || args.callgrind.is_some());
+    emu.set_coverage(args.lcov.is_some() || args.annotate.is_some());
+    emu.set_opcode_stats(
+        args.opcodes || args.opcodes_json.is_some(),
+        args.opcodes_by_function,
+    );
+    emu.set_zp_stats(args.zp_stats);
+    emu.set_stack_usage(args.stack_usage);
+    emu.set_stack_check(args.stack_check);
+    emu.set_timeline(args.timeline.is_some());
+    if args.sample_profile.is_some() {
+        emu.set_sample_period(args.sample_period.unwrap_or(Emu::DEFAULT_SAMPLE_PERIOD));
+    }
+    for (cycle, interrupt) in args.interrupts.iter().copied() {
+        emu.schedule_interrupt(cycle, interrupt);
+    }
+    // an ELF given on the command line is preloaded, otherwise the debugger
+    // is expected to upload one with `platform put-file`
+    if let Some(path) = &args.elf {
+        emu.load_elf_file(path)?;
+    }
+
+    if let args::Command::Disasm { location, count } = &args.command {
+        let lines = match location {
+            Some(location) => emu.disassemble(emu.resolve(location)?, *count),
+            None => {
+                let elf_data = std::fs::read(args.elf.as_ref().unwrap())?;
+                let elf = goblin::elf::Elf::parse(&elf_data)?;
+                let code = elf
+                    .section_headers
+                    .iter()
+                    .filter(|h| h.is_alloc() && h.is_executable());
+                let mut lines = vec![];
+                for h in code {
+                    lines.extend(emu.disassemble_range(h.sh_addr as u16, h.sh_size as u16));
+                    lines.push(String::new());
+                }
+                lines
+            }
+        };
+        for line in lines {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    if args.headless {
+        let status = match emu.run_for(args.max_cycles.unwrap_or(u64::MAX)) {
+            StopReason::Exited(status) => status as i32,
+            // like a shell reports a process killed by SIGSEGV
+            StopReason::StackFault(_) => 139,
+            _ => 124,
+        };
+        write_profile(&emu, &args)?;
+        std::process::exit(status);
+    }
+
     loop {
         let connection: Box<dyn ConnectionExt<Error = std::io::Error>> = {
-            if std::env::args().nth(1) == Some("--uds".to_string()) {
+            if args.uds {
                 #[cfg(not(unix))]
                 {
                     return Err("Unix Domain Sockets can only be used on Unix".into());
@@ -156,7 +326,7 @@ fn main() -> DynResult<()> {
             }
         };
 
-        let gdb = GdbStub::new(connection);
+        let gdb = GdbStub::new(emu.debugger_connection(connection));
 
         eprint!("gdb is ready!");
 
@@ -164,10 +334,12 @@ fn main() -> DynResult<()> {
             Ok(disconnect_reason) => match disconnect_reason {
                 DisconnectReason::Disconnect => {
                     println!("GDB client has disconnected. Running to completion...");
-                    while emu.step() != Some(emu::Event::Halted) {}
+                    while emu.step() != Some(Event::Halted) {}
+                    write_profile(&emu, &args)?;
                 }
                 DisconnectReason::TargetExited(code) => {
-                    println!("Target exited with code {}!", code)
+                    println!("Target exited with code {}!", code);
+                    write_profile(&emu, &args)?;
                 }
                 DisconnectReason::TargetTerminated(sig) => {
                     println!("Target terminated with signal {}!", sig)
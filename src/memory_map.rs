@@ -0,0 +1,131 @@
+use std::fmt::Write;
+
+use goblin::elf::program_header::PT_LOAD;
+use goblin::elf::Elf;
+
+use crate::emu::{Slot, System};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RegionKind {
+    Ram,
+    Rom,
+    Io,
+    /// open bus, left out of the map
+    Unmapped,
+}
+
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub start: u16,
+    pub len: u32,
+    pub kind: RegionKind,
+}
+
+/// Describes the whole 64K address space as a list of adjacent regions.
+#[derive(Debug, Clone)]
+pub struct MemoryMap {
+    regions: Vec<Region>,
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        Self::new(&System::default(), None)
+    }
+}
+
+impl MemoryMap {
+    /// Builds the map from the machine layout of `system`, refined with the
+    /// loadable segments of `elf` (or its allocated sections, if there are no
+    /// program headers): read-only contents placed in RAM are reported as ROM.
+    pub fn new(system: &System, elf: Option<&Elf<'_>>) -> Self {
+        let mut kinds: Vec<RegionKind> = (0..=0xffff)
+            .map(|addr| match system.slot(addr) {
+                Slot::Ram => RegionKind::Ram,
+                Slot::Rom => RegionKind::Rom,
+                Slot::OpenBus => RegionKind::Unmapped,
+                Slot::Device(..) => RegionKind::Io,
+            })
+            .collect();
+        let mut mark_read_only = |start: u64, size: u64| {
+            for addr in start..(start + size).min(0x10000) {
+                if kinds[addr as usize] == RegionKind::Ram {
+                    kinds[addr as usize] = RegionKind::Rom;
+                }
+            }
+        };
+
+        match elf {
+            Some(elf) if elf.program_headers.is_empty() => {
+                for h in elf
+                    .section_headers
+                    .iter()
+                    .filter(|h| h.is_alloc() && !h.is_writable())
+                {
+                    mark_read_only(h.sh_addr, h.sh_size);
+                }
+            }
+            Some(elf) => {
+                let segments = elf.program_headers.iter().filter(|h| h.p_type == PT_LOAD);
+                for h in segments.filter(|h| !h.is_write()) {
+                    mark_read_only(h.p_vaddr, h.p_memsz);
+                }
+            }
+            None => {}
+        }
+
+        Self::from_kinds(&kinds)
+    }
+
+    fn from_kinds(kinds: &[RegionKind]) -> Self {
+        let mut regions: Vec<Region> = vec![];
+        for (addr, kind) in kinds.iter().copied().enumerate() {
+            match regions.last_mut() {
+                Some(last) if last.kind == kind => last.len += 1,
+                _ => regions.push(Region {
+                    start: addr as u16,
+                    len: 1,
+                    kind,
+                }),
+            }
+        }
+        Self { regions }
+    }
+
+    pub fn region_at(&self, addr: u16) -> &Region {
+        self.regions
+            .iter()
+            .find(|r| (r.start as u32..r.start as u32 + r.len).contains(&(addr as u32)))
+            .expect("regions cover the address space")
+    }
+
+    pub fn kind_at(&self, addr: u16) -> RegionKind {
+        self.region_at(addr).kind
+    }
+
+    /// GDB memory map XML, LLDB asks for regions one by one with
+    /// `qMemoryRegionInfo` instead. GDB has no notion of I/O memory, so ports
+    /// are reported as RAM, open bus areas are left out.
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from(
+            r#"<?xml version="1.0"?>
+<!DOCTYPE memory-map PUBLIC "+//IDN gnu.org//DTD GDB Memory Map V1.0//EN" "http://sourceware.org/gdb/gdb-memory-map.dtd">
+<memory-map>
+"#,
+        );
+        for region in &self.regions {
+            let kind = match region.kind {
+                RegionKind::Rom => "rom",
+                RegionKind::Ram | RegionKind::Io => "ram",
+                RegionKind::Unmapped => continue,
+            };
+            writeln!(
+                xml,
+                r#"  <memory type="{}" start="{:#06x}" length="{:#x}"/>"#,
+                kind, region.start, region.len
+            )
+            .unwrap();
+        }
+        xml.push_str("</memory-map>\n");
+        xml
+    }
+}
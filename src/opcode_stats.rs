@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::disasm::opcode_info;
+use crate::symbols::Symbols;
+use crate::timeline::json_string;
+
+/// Executions and cycles of one opcode or addressing mode
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct OpStats {
+    pub count: u64,
+    pub cycles: u64,
+}
+
+impl OpStats {
+    fn add(&mut self, other: OpStats) {
+        self.count += other.count;
+        self.cycles += other.cycles;
+    }
+}
+
+/// Executions and cycles per opcode, optionally broken down by the function
+/// (symbol) the instructions are in.
+#[derive(Debug)]
+pub struct OpcodeStats {
+    enabled: bool,
+    per_function: bool,
+    opcodes: Vec<OpStats>,
+    /// per opcode stats of every function, `None` for code outside symbols
+    funcs: HashMap<Option<u16>, Vec<OpStats>>,
+}
+
+impl Default for OpcodeStats {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            per_function: false,
+            opcodes: vec![OpStats::default(); 256],
+            funcs: HashMap::new(),
+        }
+    }
+}
+
+impl OpcodeStats {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool, per_function: bool) {
+        self.enabled = enabled;
+        self.per_function = per_function;
+    }
+
+    pub fn reset(&mut self) {
+        self.opcodes.fill(OpStats::default());
+        self.funcs.clear();
+    }
+
+    /// Instruction `opcode` at `pc` ran, taking `cycles`.
+    pub fn instruction(&mut self, symbols: &Symbols, pc: u16, opcode: u8, cycles: u64) {
+        let stats = OpStats { count: 1, cycles };
+        self.opcodes[opcode as usize].add(stats);
+        if self.per_function {
+            let func = symbols.lookup(pc).map(|(sym, _)| sym.addr);
+            let opcodes = self
+                .funcs
+                .entry(func)
+                .or_insert_with(|| vec![OpStats::default(); 256]);
+            opcodes[opcode as usize].add(stats);
+        }
+    }
+
+    /// Table of opcodes and addressing modes by executions, followed by the
+    /// opcodes of every function when broken down by function.
+    pub fn report(&self, symbols: &Symbols) -> String {
+        let mut report = opcode_table(&self.opcodes);
+        report += "\n";
+        report += &format!(
+            "{:>12} {:>6} {:>12} {:>6}  mode\n",
+            "count", "%", "cycles", "%"
+        );
+        let total = total(&self.opcodes);
+        for (mode, stats) in modes(&self.opcodes) {
+            report += &format!(
+                "{:>12} {:>6.2} {:>12} {:>6.2}  {}\n",
+                stats.count,
+                percent(stats.count, total.count),
+                stats.cycles,
+                percent(stats.cycles, total.cycles),
+                mode
+            );
+        }
+        for (func, opcodes) in self.functions() {
+            report += &format!("\n{}:\n", symbols.func_name(func));
+            report += &opcode_table(opcodes);
+        }
+        report
+    }
+
+    /// Writes the stats as JSON: `opcodes` and `modes` arrays, and
+    /// `functions` when broken down by function.
+    pub fn write_json(&self, symbols: &Symbols, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"opcodes\": [{}],", opcodes_json(&self.opcodes))?;
+        let modes: Vec<_> = modes(&self.opcodes)
+            .iter()
+            .map(|(mode, stats)| {
+                format!(
+                    "{{\"mode\": \"{}\", \"count\": {}, \"cycles\": {}}}",
+                    mode, stats.count, stats.cycles
+                )
+            })
+            .collect();
+        write!(out, "  \"modes\": [{}]", modes.join(", "))?;
+        if self.per_function {
+            writeln!(out, ",")?;
+            writeln!(out, "  \"functions\": [")?;
+            let funcs = self.functions();
+            for (i, (func, opcodes)) in funcs.iter().enumerate() {
+                let addr = func.map(|addr| format!("\"{:#06x}\"", addr));
+                write!(
+                    out,
+                    "    {{\"name\": {}, \"addr\": {}, \"opcodes\": [{}]}}",
+                    json_string(&symbols.func_name(*func)),
+                    addr.as_deref().unwrap_or("null"),
+                    opcodes_json(opcodes)
+                )?;
+                writeln!(out, "{}", if i + 1 < funcs.len() { "," } else { "" })?;
+            }
+            write!(out, "  ]")?;
+        }
+        writeln!(out)?;
+        writeln!(out, "}}")
+    }
+
+    /// functions by cycles spent in them
+    fn functions(&self) -> Vec<(Option<u16>, &[OpStats])> {
+        let mut funcs: Vec<_> = self
+            .funcs
+            .iter()
+            .map(|(func, opcodes)| (*func, opcodes.as_slice()))
+            .collect();
+        funcs.sort_by_key(|(func, opcodes)| (std::cmp::Reverse(total(opcodes).cycles), *func));
+        funcs
+    }
+}
+
+/// mnemonic and addressing mode syntax of `opcode`
+fn opcode_name(opcode: u8) -> (&'static str, &'static str) {
+    match opcode_info(opcode) {
+        Some((mnemonic, mode)) => (mnemonic, mode.syntax()),
+        None => ("???", "illegal"),
+    }
+}
+
+/// opcodes that ran, by executions
+fn executed(opcodes: &[OpStats]) -> Vec<(u8, OpStats)> {
+    let mut executed: Vec<_> = (0..=255)
+        .zip(opcodes.iter().copied())
+        .filter(|(_, stats)| stats.count > 0)
+        .collect();
+    executed.sort_by_key(|(opcode, stats)| (std::cmp::Reverse(stats.count), *opcode));
+    executed
+}
+
+/// stats summed per addressing mode, by executions
+fn modes(opcodes: &[OpStats]) -> Vec<(&'static str, OpStats)> {
+    let mut modes: Vec<(&'static str, OpStats)> = vec![];
+    for (opcode, stats) in executed(opcodes) {
+        let mode = opcode_name(opcode).1;
+        match modes.iter_mut().find(|(m, _)| *m == mode) {
+            Some((_, total)) => total.add(stats),
+            None => modes.push((mode, stats)),
+        }
+    }
+    modes.sort_by_key(|(mode, stats)| (std::cmp::Reverse(stats.count), *mode));
+    modes
+}
+
+fn total(opcodes: &[OpStats]) -> OpStats {
+    let mut total = OpStats::default();
+    for stats in opcodes {
+        total.add(*stats);
+    }
+    total
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    part as f64 * 100.0 / total.max(1) as f64
+}
+
+fn opcode_table(opcodes: &[OpStats]) -> String {
+    let total = total(opcodes);
+    let mut table = format!(
+        "{:>12} {:>6} {:>12} {:>6}  opcode\n",
+        "count", "%", "cycles", "%"
+    );
+    for (opcode, stats) in executed(opcodes) {
+        let (mnemonic, mode) = opcode_name(opcode);
+        table += &format!(
+            "{:>12} {:>6.2} {:>12} {:>6.2}  {:02x} {} {}\n",
+            stats.count,
+            percent(stats.count, total.count),
+            stats.cycles,
+            percent(stats.cycles, total.cycles),
+            opcode,
+            mnemonic,
+            mode
+        );
+    }
+    table
+}
+
+fn opcodes_json(opcodes: &[OpStats]) -> String {
+    let entries: Vec<_> = executed(opcodes)
+        .iter()
+        .map(|(opcode, stats)| {
+            let (mnemonic, mode) = opcode_name(*opcode);
+            format!(
+                "{{\"opcode\": \"{:#04x}\", \"mnemonic\": \"{}\", \"mode\": \"{}\", \"count\": {}, \"cycles\": {}}}",
+                opcode, mnemonic, mode, stats.count, stats.cycles
+            )
+        })
+        .collect();
+    entries.join(", ")
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::symbols::Symbols;
+
+/// Cycles and calls of one function
+#[derive(Debug, Default, Clone)]
+pub struct FuncStats {
+    pub calls: u64,
+    /// cycles spent in the function itself
+    pub exclusive: u64,
+    /// cycles including callees, recursive calls counted once
+    pub inclusive: u64,
+}
+
+/// Calls from one function to another
+#[derive(Debug, Default, Clone)]
+pub struct CallStats {
+    pub calls: u64,
+    pub inclusive: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    /// entry address of the function
+    func: u16,
+    /// profiler clock at entry
+    start: u64,
+}
+
+/// Function level profiler.
+///
+/// Charges the cycles of every instruction to the function it ran in, with
+/// calls and returns reported by the emulator's `CallStack`. A function is
+/// identified by the address it was called at.
+#[derive(Debug, Default, Clone)]
+pub struct Profiler {
+    enabled: bool,
+    /// cycles profiled so far
+    clock: u64,
+    stack: Vec<Frame>,
+    funcs: HashMap<u16, FuncStats>,
+    /// keyed by (caller, callee)
+    calls: HashMap<(u16, u16), CallStats>,
+}
+
+impl Profiler {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Starts or stops profiling; when nothing was collected yet, profiling
+    /// starts in function `pc`.
+    pub fn set_enabled(&mut self, enabled: bool, pc: u16) {
+        self.enabled = enabled;
+        if self.stack.is_empty() {
+            self.reset(pc);
+        }
+    }
+
+    /// Drops collected data, profiling continues in function `pc`.
+    pub fn reset(&mut self, pc: u16) {
+        *self = Self {
+            enabled: self.enabled,
+            ..Default::default()
+        };
+        self.call(pc);
+    }
+
+    /// Charges `cycles` to the current function.
+    pub fn instruction(&mut self, cycles: u64) {
+        self.clock += cycles;
+        if let Some(frame) = self.stack.last() {
+            self.funcs.entry(frame.func).or_default().exclusive += cycles;
+        }
+    }
+
+    /// Function `func` was entered.
+    pub fn call(&mut self, func: u16) {
+        self.funcs.entry(func).or_default().calls += 1;
+        self.stack.push(Frame {
+            func,
+            start: self.clock,
+        });
+    }
+
+    /// The innermost function returned. The function profiling started in
+    /// is never left, its callers weren't seen.
+    pub fn ret(&mut self) {
+        if self.stack.len() > 1 {
+            self.pop();
+        }
+    }
+
+    fn pop(&mut self) {
+        let frame = self.stack.pop().unwrap();
+        let elapsed = self.clock - frame.start;
+        if !self.stack.iter().any(|outer| outer.func == frame.func) {
+            self.funcs.entry(frame.func).or_default().inclusive += elapsed;
+        }
+        if let Some(caller) = self.stack.last() {
+            let call = self.calls.entry((caller.func, frame.func)).or_default();
+            call.calls += 1;
+            call.inclusive += elapsed;
+        }
+    }
+
+    /// Profile with functions still running counted up to now.
+    fn completed(&self) -> Profiler {
+        let mut profile = self.clone();
+        while !profile.stack.is_empty() {
+            profile.pop();
+        }
+        profile
+    }
+
+    /// Stats per function entry address, functions still running counted
+    /// up to now.
+    pub fn function_stats(&self) -> HashMap<u16, FuncStats> {
+        self.completed().funcs
+    }
+
+    /// Table of functions by inclusive cycles.
+    pub fn report(&self, symbols: &Symbols) -> String {
+        let profile = self.completed();
+        let total = profile.clock.max(1) as f64;
+        let mut funcs: Vec<_> = profile.funcs.iter().collect();
+        funcs.sort_by_key(|(addr, stats)| (std::cmp::Reverse(stats.inclusive), **addr));
+
+        let mut report = format!(
+            "{:>12} {:>6} {:>12} {:>6} {:>8}  function\n",
+            "inclusive", "%", "exclusive", "%", "calls"
+        );
+        for (addr, stats) in funcs {
+            report += &format!(
+                "{:>12} {:>6.2} {:>12} {:>6.2} {:>8}  {}\n",
+                stats.inclusive,
+                stats.inclusive as f64 * 100.0 / total,
+                stats.exclusive,
+                stats.exclusive as f64 * 100.0 / total,
+                stats.calls,
+                symbols.func_name(Some(*addr))
+            );
+        }
+        report
+    }
+
+    /// Writes the profile in callgrind format, for KCachegrind.
+    pub fn write_callgrind(&self, symbols: &Symbols, out: &mut dyn Write) -> std::io::Result<()> {
+        let profile = self.completed();
+        writeln!(out, "# callgrind format")?;
+        writeln!(out, "version: 1")?;
+        writeln!(out, "creator: sim6502")?;
+        writeln!(out, "positions: instr")?;
+        writeln!(out, "events: Cycles")?;
+        writeln!(out, "summary: {}", profile.clock)?;
+
+        let mut funcs: Vec<_> = profile.funcs.iter().collect();
+        funcs.sort_by_key(|(addr, _)| **addr);
+        let mut calls: Vec<_> = profile.calls.iter().collect();
+        calls.sort_by_key(|(key, _)| **key);
+        for (addr, stats) in funcs {
+            writeln!(out)?;
+            writeln!(out, "fn={}", symbols.func_name(Some(*addr)))?;
+            writeln!(out, "{:#06x} {}", addr, stats.exclusive)?;
+            for ((_, callee), call) in calls.iter().filter(|((caller, _), _)| caller == addr) {
+                writeln!(out, "cfn={}", symbols.func_name(Some(*callee)))?;
+                writeln!(out, "calls={} {:#06x}", call.calls, callee)?;
+                writeln!(out, "{:#06x} {}", addr, call.inclusive)?;
+            }
+        }
+        Ok(())
+    }
+}
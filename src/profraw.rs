@@ -0,0 +1,291 @@
+use std::io::Write;
+use std::ops::Range;
+
+use goblin::elf::Elf;
+
+use crate::DynResult;
+
+/// `INSTR_PROF_RAW_MAGIC_64`
+const MAGIC_64: u64 = (255 << 56)
+    | ((b'l' as u64) << 48)
+    | ((b'p' as u64) << 40)
+    | ((b'r' as u64) << 32)
+    | ((b'o' as u64) << 24)
+    | ((b'f' as u64) << 16)
+    | ((b'r' as u64) << 8)
+    | 129;
+/// counters are single bytes (`-enable-single-byte-coverage`)
+const VARIANT_MASK_BYTE_COVERAGE: u64 = 1 << 60;
+const VERSION_MASK: u64 = 0xff;
+
+/// Addresses of the instrumentation sections of the loaded program.
+#[derive(Debug, Default, Clone)]
+pub struct ProfSections {
+    /// per-function records (`__llvm_profile_data`)
+    data: Range<u16>,
+    counters: Range<u16>,
+    /// MC/DC bitmaps, version 9 and later
+    bitmap: Range<u16>,
+    /// encoded function names
+    names: Range<u16>,
+}
+
+impl ProfSections {
+    pub fn from_elf(elf: &Elf<'_>) -> Self {
+        let section = |name| {
+            elf.section_headers
+                .iter()
+                .find(|h| elf.shdr_strtab.get_at(h.sh_name) == Some(name))
+                .map(|h| h.sh_addr as u16..(h.sh_addr + h.sh_size) as u16)
+                .unwrap_or(0..0)
+        };
+        Self {
+            data: section("__llvm_prf_data"),
+            counters: section("__llvm_prf_cnts"),
+            bitmap: section("__llvm_prf_bits"),
+            names: section("__llvm_prf_names"),
+        }
+    }
+
+    /// whether the program was built with `-fprofile-instr-generate`
+    pub fn is_present(&self) -> bool {
+        !self.data.is_empty()
+    }
+}
+
+fn padding(len: usize) -> usize {
+    (8 - len % 8) % 8
+}
+
+/// Writes a raw profile llvm-profdata can read, converting the packed
+/// 16-bit records of the target to the 64-bit layout.
+///
+/// `raw_version` is the value of `__llvm_profile_raw_version` of the
+/// program; raw versions 8 (LLVM 15-17), 9 (LLVM 18) and 10 (LLVM 19+) are
+/// understood. `peek` reads target memory.
+pub fn write_profraw(
+    sections: &ProfSections,
+    raw_version: u64,
+    peek: impl Fn(u16) -> u8,
+    out: &mut dyn Write,
+) -> DynResult<()> {
+    let version = raw_version & VERSION_MASK;
+    // record sizes: packed target layout, 64-bit layout (8 byte aligned)
+    let (value_kinds, target_size, host_size) = match version {
+        8 => (2, 30, 48),
+        9 => (2, 36, 64),
+        10 => (3, 38, 64),
+        _ => return Err(format!("unsupported raw profile version {}", version).into()),
+    };
+    let has_bitmap = version >= 9;
+
+    let read = |range: &Range<u16>| -> Vec<u8> { range.clone().map(&peek).collect() };
+    let data = read(&sections.data);
+    let counters = read(&sections.counters);
+    let bitmap = read(&sections.bitmap);
+    let names = read(&sections.names);
+    if data.len() % target_size != 0 {
+        return Err(format!(
+            "size of __llvm_prf_data ({}) is not a multiple of the version {} record size",
+            data.len(),
+            version
+        )
+        .into());
+    }
+    let num_data = data.len() / target_size;
+
+    // layout of the file, as if it was the memory image of a 64-bit program
+    // with the data records at address 0
+    let data_size = num_data * host_size;
+    let counters_delta = data_size;
+    let bitmap_delta = counters_delta + counters.len() + padding(counters.len());
+    let names_delta = bitmap_delta + bitmap.len() + padding(bitmap.len());
+
+    let mut header = vec![
+        MAGIC_64,
+        raw_version,
+        0, // binary ids size
+        num_data as u64,
+        0, // padding before counters
+        (counters.len()
+            / if raw_version & VARIANT_MASK_BYTE_COVERAGE != 0 {
+                1
+            } else {
+                8
+            }) as u64,
+        padding(counters.len()) as u64,
+    ];
+    if has_bitmap {
+        header.extend([bitmap.len() as u64, padding(bitmap.len()) as u64]);
+    }
+    header.extend([names.len() as u64, counters_delta as u64]);
+    if has_bitmap {
+        header.push(bitmap_delta as u64);
+    }
+    header.push(names_delta as u64);
+    if version >= 10 {
+        // no vtable profiles: number of vtables, size of their names
+        header.extend([0, 0]);
+    }
+    header.push(value_kinds - 1);
+    for field in header {
+        out.write_all(&field.to_le_bytes())?;
+    }
+
+    for i in 0..num_data {
+        let record = &data[i * target_size..(i + 1) * target_size];
+        let record_addr = sections.data.start.wrapping_add((i * target_size) as u16);
+        let u16_at = |offset: usize| u16::from_le_bytes([record[offset], record[offset + 1]]);
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(record[offset..offset + 4].try_into().unwrap());
+        // pointers to counters and bitmap are relative to the record
+        let target_offset = |rel: u16, section: &Range<u16>| {
+            record_addr.wrapping_add(rel).wrapping_sub(section.start) as i64
+        };
+        let host_addr = (i * host_size) as i64;
+
+        let mut fields = 16;
+        let counter_ptr = counters_delta as i64 + target_offset(u16_at(fields), &sections.counters);
+        fields += 2;
+        let mut bitmap_ptr = None;
+        if has_bitmap {
+            bitmap_ptr =
+                Some(bitmap_delta as i64 + target_offset(u16_at(fields), &sections.bitmap));
+            fields += 2;
+        }
+        let function_ptr = u16_at(fields);
+        fields += 4; // function pointer, values
+        let num_counters = u32_at(fields);
+        fields += 4;
+        let value_sites: Vec<u16> = (0..value_kinds as usize)
+            .map(|k| u16_at(fields + k * 2))
+            .collect();
+        fields += value_kinds as usize * 2;
+
+        let mut host_record = record[..16].to_vec();
+        host_record.extend((counter_ptr - host_addr).to_le_bytes());
+        if let Some(bitmap_ptr) = bitmap_ptr {
+            let num_bitmap_bytes = u32_at(fields);
+            let ptr = if num_bitmap_bytes > 0 {
+                bitmap_ptr
+            } else {
+                bitmap_delta as i64
+            };
+            host_record.extend((ptr - host_addr).to_le_bytes());
+        }
+        host_record.extend((function_ptr as u64).to_le_bytes());
+        host_record.extend(0u64.to_le_bytes()); // values, not collected
+        host_record.extend(num_counters.to_le_bytes());
+        for sites in value_sites {
+            host_record.extend(sites.to_le_bytes());
+        }
+        if has_bitmap {
+            // u32 alignment
+            host_record.resize(host_record.len().next_multiple_of(4), 0);
+            host_record.extend(&record[fields..fields + 4]);
+        }
+        host_record.resize(host_size, 0);
+        out.write_all(&host_record)?;
+    }
+
+    for section in [&counters, &bitmap, &names] {
+        out.write_all(section)?;
+        out.write_all(&vec![0; padding(section.len())])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME_REF: u64 = 0x1122_3344_5566_7788;
+    const FUNC_HASH: u64 = 0x99aa_bbcc_ddee_ff00;
+
+    /// Target memory with one function record of `version` at 0x1000, two
+    /// counters at 0x2000, one bitmap byte at 0x2100 and names at 0x2200.
+    fn program(version: u64) -> (ProfSections, Vec<u8>) {
+        let mut record = [NAME_REF.to_le_bytes(), FUNC_HASH.to_le_bytes()].concat();
+        record.extend(0x1000u16.to_le_bytes()); // counters, relative
+        if version >= 9 {
+            record.extend(0x1100u16.to_le_bytes()); // bitmap, relative
+        }
+        record.extend(0x0800u16.to_le_bytes()); // function
+        record.extend(0u16.to_le_bytes()); // values
+        record.extend(2u32.to_le_bytes()); // counters
+        let value_kinds = if version >= 10 { 3 } else { 2 };
+        record.extend(vec![0; value_kinds * 2]);
+        if version >= 9 {
+            record.extend(1u32.to_le_bytes()); // bitmap bytes
+        }
+
+        let mut mem = vec![0; 0x10000];
+        mem[0x1000..0x1000 + record.len()].copy_from_slice(&record);
+        mem[0x2000..0x2010].copy_from_slice(&[[5, 0, 0, 0, 0, 0, 0, 0], [7; 8]].concat());
+        mem[0x2100] = 0x81;
+        mem[0x2200..0x2203].copy_from_slice(b"abc");
+        let sections = ProfSections {
+            data: 0x1000..0x1000 + record.len() as u16,
+            counters: 0x2000..0x2010,
+            bitmap: if version >= 9 { 0x2100..0x2101 } else { 0..0 },
+            names: 0x2200..0x2203,
+        };
+        (sections, mem)
+    }
+
+    fn profraw(version: u64) -> Vec<u8> {
+        let (sections, mem) = program(version);
+        let mut out = vec![];
+        write_profraw(&sections, version, |addr| mem[addr as usize], &mut out).unwrap();
+        out
+    }
+
+    fn u64s(fields: &[u64]) -> Vec<u8> {
+        fields
+            .iter()
+            .flat_map(|field| field.to_le_bytes())
+            .collect()
+    }
+
+    /// counters, bitmap and names sections, each padded to 8 bytes
+    fn sections(bitmap: &[u8]) -> Vec<u8> {
+        let mut out = [[5, 0, 0, 0, 0, 0, 0, 0], [7; 8]].concat();
+        out.extend(bitmap);
+        out.extend(b"abc\0\0\0\0\0");
+        out
+    }
+
+    #[test]
+    fn version_8() {
+        let mut expected = u64s(&[MAGIC_64, 8, 0, 1, 0, 2, 0, 3, 48, 64, 1]);
+        expected.extend(u64s(&[NAME_REF, FUNC_HASH, 48, 0x800, 0]));
+        expected.extend([2, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend(sections(&[]));
+        assert_eq!(profraw(8), expected);
+    }
+
+    #[test]
+    fn version_9() {
+        let mut expected = u64s(&[MAGIC_64, 9, 0, 1, 0, 2, 0, 1, 7, 3, 64, 80, 88, 1]);
+        expected.extend(u64s(&[NAME_REF, FUNC_HASH, 64, 80, 0x800, 0]));
+        expected.extend([2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend(sections(&[0x81, 0, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(profraw(9), expected);
+    }
+
+    #[test]
+    fn version_10() {
+        let mut expected = u64s(&[MAGIC_64, 10, 0, 1, 0, 2, 0, 1, 7, 3, 64, 80, 88, 0, 0, 2]);
+        expected.extend(u64s(&[NAME_REF, FUNC_HASH, 64, 80, 0x800, 0]));
+        expected.extend([2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0]);
+        expected.extend(sections(&[0x81, 0, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(profraw(10), expected);
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let (sections, mem) = program(8);
+        let mut out = vec![];
+        assert!(write_profraw(&sections, 7, |addr| mem[addr as usize], &mut out).is_err());
+    }
+}
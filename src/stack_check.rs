@@ -0,0 +1,308 @@
+use crate::disasm::{opcode_info, Mode};
+
+/// Misuse of the hardware stack caught by `StackCheck`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StackFault {
+    /// S wrapped from `$0100` to `$01ff`
+    Overflow,
+    /// S wrapped from `$01ff` to `$0100`
+    Underflow,
+    /// `RTS`/`RTI` to `to` while a tracked call's return address is not on
+    /// top of the stack, or through one that was changed since or pushed by
+    /// the other kind of call
+    BadReturn { to: u16 },
+    /// return address at `addr` overwritten by an instruction other than a
+    /// push
+    Overwritten { addr: u16 },
+}
+
+impl std::fmt::Display for StackFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StackFault::Overflow => write!(f, "stack overflow"),
+            StackFault::Underflow => write!(f, "stack underflow"),
+            StackFault::BadReturn { to } => {
+                write!(f, "return to {:04x}, not where called from", to)
+            }
+            StackFault::Overwritten { addr } => {
+                write!(f, "return address at {:04x} overwritten", addr)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    /// S after the return address was pushed
+    sp: u8,
+    /// return address as stored on the stack (JSR pushes its own last byte)
+    ret: u16,
+    /// pushed by `BRK` or an interrupt, with P below the return address
+    interrupt: bool,
+}
+
+impl Frame {
+    /// stack addresses of the low and high byte of the return address
+    fn slots(&self) -> (u16, u16) {
+        let lo = self.sp.wrapping_add(if self.interrupt { 2 } else { 1 });
+        (0x100 | lo as u16, 0x100 | lo.wrapping_add(1) as u16)
+    }
+
+    /// return address currently in the stack slots
+    fn stored(&self, peek: impl Fn(u16) -> u8) -> u16 {
+        let (lo, hi) = self.slots();
+        u16::from_le_bytes([peek(lo), peek(hi)])
+    }
+}
+
+/// Shadow call stack of the return addresses pushed by `JSR`, `BRK` and
+/// interrupts, checked against the hardware stack after every instruction.
+#[derive(Debug, Default)]
+pub struct StackCheck {
+    enabled: bool,
+    frames: Vec<Frame>,
+    fault: Option<StackFault>,
+}
+
+impl StackCheck {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Starts or stops checking. Calls made while it was off are not known,
+    /// so checking starts with an empty shadow stack.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.frames.clear();
+        }
+        self.enabled = enabled;
+    }
+
+    pub fn reset(&mut self) {
+        self.frames.clear();
+        self.fault = None;
+    }
+
+    /// Fault found since the last call.
+    pub fn take_fault(&mut self) -> Option<StackFault> {
+        self.fault.take()
+    }
+
+    /// A return address for a call was pushed outside of `instruction`, S is
+    /// now `sp`.
+    pub fn call(&mut self, sp: u8, peek: impl Fn(u16) -> u8) {
+        self.push_frame(sp, false, peek);
+    }
+
+    /// An interrupt pushed PC and P, moving S from `sp` to `new_sp`.
+    pub fn interrupt(&mut self, sp: u8, new_sp: u8, peek: impl Fn(u16) -> u8) {
+        if sp < 3 {
+            self.fault = Some(StackFault::Overflow);
+        }
+        self.push_frame(new_sp, true, peek);
+    }
+
+    fn push_frame(&mut self, sp: u8, interrupt: bool, peek: impl Fn(u16) -> u8) {
+        let mut frame = Frame {
+            sp,
+            ret: 0,
+            interrupt,
+        };
+        frame.ret = frame.stored(peek);
+        self.frames.push(frame);
+    }
+
+    /// `opcode` ran, moving S from `sp` to `new_sp` and PC to `new_pc`.
+    pub fn instruction(
+        &mut self,
+        opcode: u8,
+        sp: u8,
+        new_sp: u8,
+        new_pc: u16,
+        peek: impl Fn(u16) -> u8,
+    ) {
+        let (pushed, popped) = match opcode {
+            0x00 => (3, 0),
+            0x20 => (2, 0),
+            0x08 | 0x48 => (1, 0),
+            0x28 | 0x68 => (0, 1),
+            0x60 => (0, 2),
+            0x40 => (0, 3),
+            _ => (0, 0),
+        };
+        if pushed > sp {
+            self.fault = Some(StackFault::Overflow);
+        } else if sp as u16 + popped as u16 > 0xff {
+            self.fault = Some(StackFault::Underflow);
+        }
+
+        match opcode {
+            // JSR, BRK
+            0x20 | 0x00 => self.push_frame(new_sp, opcode == 0x00, peek),
+            // RTS, RTI
+            0x60 | 0x40 => {
+                let interrupt = opcode == 0x40;
+                // frames popped some other way (PLA, TXS) are gone
+                self.frames.retain(|frame| frame.sp >= sp);
+                let ret = if interrupt {
+                    new_pc
+                } else {
+                    new_pc.wrapping_sub(1)
+                };
+                // the caller of the function running when the check was
+                // turned on isn't known, so only returns made while tracked
+                // calls are pending are checked; those must go through the
+                // innermost one (a pushed address left on top, as by code
+                // jumping with `RTS`, is reported)
+                if let Some(frame) = self.frames.last() {
+                    if frame.sp != sp || frame.interrupt != interrupt || frame.ret != ret {
+                        self.fault = Some(StackFault::BadReturn { to: new_pc });
+                    }
+                    if frame.sp == sp {
+                        self.frames.pop();
+                    }
+                }
+            }
+            _ if is_store(opcode) => {
+                self.frames.retain(|frame| frame.sp >= new_sp);
+                for frame in &mut self.frames {
+                    let ret = frame.stored(&peek);
+                    if ret != frame.ret {
+                        // reported once, the return address is what it is now
+                        frame.ret = ret;
+                        self.fault = Some(StackFault::Overwritten {
+                            addr: frame.slots().0,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// whether `opcode` writes memory other than by pushing
+fn is_store(opcode: u8) -> bool {
+    matches!(
+        opcode_info(opcode),
+        Some(("sta" | "stx" | "sty" | "inc" | "dec" | "asl" | "lsr" | "rol" | "ror", mode)) if mode != Mode::Accumulator
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// page 1 and S, driven through `StackCheck` like the emulator does
+    struct Cpu {
+        stack: [u8; 0x100],
+        sp: u8,
+        pc: u16,
+        check: StackCheck,
+    }
+
+    impl Cpu {
+        fn new() -> Self {
+            let mut check = StackCheck::default();
+            check.set_enabled(true);
+            Cpu {
+                stack: [0; 0x100],
+                // below the return address of the caller
+                sp: 0xfd,
+                pc: 0x0800,
+                check,
+            }
+        }
+
+        fn push(&mut self, val: u8) {
+            self.stack[self.sp as usize] = val;
+            self.sp = self.sp.wrapping_sub(1);
+        }
+
+        fn pull(&mut self) -> u8 {
+            self.sp = self.sp.wrapping_add(1);
+            self.stack[self.sp as usize]
+        }
+
+        fn run(&mut self, opcode: u8, sp: u8) {
+            let stack = self.stack;
+            self.check
+                .instruction(opcode, sp, self.sp, self.pc, |addr| {
+                    stack[addr as usize & 0xff]
+                });
+        }
+
+        fn jsr(&mut self, to: u16) {
+            let (sp, ret) = (self.sp, self.pc + 2);
+            self.push((ret >> 8) as u8);
+            self.push(ret as u8);
+            self.pc = to;
+            self.run(0x20, sp);
+        }
+
+        fn rts(&mut self) {
+            let sp = self.sp;
+            let ret = u16::from_le_bytes([self.pull(), self.pull()]);
+            self.pc = ret.wrapping_add(1);
+            self.run(0x60, sp);
+        }
+
+        fn pha(&mut self, val: u8) {
+            let sp = self.sp;
+            self.push(val);
+            self.pc += 1;
+            self.run(0x48, sp);
+        }
+
+        /// `STA $01xx`
+        fn sta(&mut self, addr: u8, val: u8) {
+            self.stack[addr as usize] = val;
+            self.pc += 3;
+            self.run(0x8d, self.sp);
+        }
+    }
+
+    #[test]
+    fn balanced() {
+        let mut cpu = Cpu::new();
+        cpu.jsr(0x0900);
+        cpu.pha(1);
+        cpu.jsr(0x0a00);
+        cpu.rts();
+        // local variable next to the return addresses
+        cpu.sta(0xfb, 2);
+        assert_eq!(cpu.pull(), 2);
+        cpu.rts();
+        assert_eq!(cpu.pc, 0x0803);
+        // return of the function running when the check was turned on
+        cpu.rts();
+        assert_eq!(cpu.check.take_fault(), None);
+    }
+
+    #[test]
+    fn extra_push() {
+        let mut cpu = Cpu::new();
+        cpu.jsr(0x0900);
+        // jump to 0x0a00 the RTS way
+        cpu.pha(0x09);
+        cpu.pha(0xff);
+        cpu.rts();
+        assert_eq!(
+            cpu.check.take_fault(),
+            Some(StackFault::BadReturn { to: 0x0a00 })
+        );
+    }
+
+    #[test]
+    fn overwritten_return_address() {
+        let mut cpu = Cpu::new();
+        cpu.jsr(0x0900);
+        cpu.sta(0xfc, 0x40);
+        assert_eq!(
+            cpu.check.take_fault(),
+            Some(StackFault::Overwritten { addr: 0x01fc })
+        );
+        cpu.rts();
+        assert_eq!(cpu.check.take_fault(), None);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::symbols::Symbols;
+
+/// Lowest stack pointer seen, with where it happened
+#[derive(Debug, Clone)]
+pub struct Peak {
+    pub sp: u16,
+    /// instruction that left the stack pointer there
+    pub pc: u16,
+    /// entry addresses of the functions running, outermost first
+    pub chain: Vec<u16>,
+}
+
+/// High-water marks of the hardware stack (S) and of the llvm-mos soft stack
+/// (`__rs0`, i.e. `__rc0`/`__rc1`).
+///
+/// The call chain reaching each peak is taken from the functions the
+/// emulator follows for all profilers.
+#[derive(Debug, Default)]
+pub struct StackUsage {
+    enabled: bool,
+    hardware: Option<Peak>,
+    soft: Option<Peak>,
+    /// initial soft stack pointer (`__stack`), if known
+    soft_top: Option<u16>,
+    /// highest soft stack pointer seen, stands in for `soft_top`
+    soft_max: u16,
+}
+
+impl StackUsage {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Forgets the peaks. `soft_top` is the initial soft stack pointer.
+    pub fn reset(&mut self, soft_top: Option<u16>) {
+        *self = Self {
+            enabled: self.enabled,
+            soft_top,
+            ..Default::default()
+        };
+    }
+
+    /// Instruction at `pc` left the stack pointers at `sp` and `soft_sp`
+    /// (0 while the program hasn't set it up), in the functions `chain`
+    /// (outermost first).
+    pub fn update(
+        &mut self,
+        pc: u16,
+        sp: u8,
+        soft_sp: Option<u16>,
+        chain: impl Iterator<Item = u16> + Clone,
+    ) {
+        if !matches!(&self.hardware, Some(peak) if peak.sp <= sp as u16) {
+            self.hardware = Some(Peak {
+                sp: sp as u16,
+                pc,
+                chain: chain.clone().collect(),
+            });
+        }
+        if let Some(soft_sp) = soft_sp.filter(|soft_sp| *soft_sp != 0) {
+            self.soft_max = self.soft_max.max(soft_sp);
+            if !matches!(&self.soft, Some(peak) if peak.sp <= soft_sp) {
+                self.soft = Some(Peak {
+                    sp: soft_sp,
+                    pc,
+                    chain: chain.collect(),
+                });
+            }
+        }
+    }
+
+    /// Peak depth of both stacks, where it was reached and the call chain
+    /// leading there.
+    pub fn report(&self, symbols: &Symbols) -> String {
+        let mut report = String::new();
+        match &self.hardware {
+            Some(peak) => {
+                report += &format!(
+                    "hardware stack: {} bytes (S = ${:02x})\n",
+                    0xff - peak.sp,
+                    peak.sp
+                );
+                report += &describe(symbols, peak);
+            }
+            None => report += "hardware stack: not used\n",
+        }
+        match &self.soft {
+            Some(peak) => {
+                let top = self.soft_top.unwrap_or(self.soft_max);
+                report += &format!(
+                    "soft stack: {} bytes (__rs0 = ${:04x}, {} ${:04x})\n",
+                    top.wrapping_sub(peak.sp),
+                    peak.sp,
+                    if self.soft_top.is_some() {
+                        "__stack ="
+                    } else {
+                        "highest seen"
+                    },
+                    top
+                );
+                report += &describe(symbols, peak);
+            }
+            None => report += "soft stack: not used\n",
+        }
+        report
+    }
+}
+
+/// location and call chain of `peak`
+fn describe(symbols: &Symbols, peak: &Peak) -> String {
+    let chain: Vec<_> = peak
+        .chain
+        .iter()
+        .map(|func| symbols.func_name(Some(*func)))
+        .collect();
+    let location = match symbols.symbolize(peak.pc) {
+        Some(sym) => format!("{:04x} ({})", peak.pc, sym),
+        None => format!("{:04x}", peak.pc),
+    };
+    format!("  at {}\n  call chain: {}\n", location, chain.join(" > "))
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use goblin::elf::sym::{STT_FILE, STT_FUNC, STT_SECTION};
+use goblin::elf::Elf;
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u16,
+    /// 0 if unknown
+    pub size: u16,
+    /// code rather than data
+    pub func: bool,
+}
+
+/// Named addresses of the loaded program.
+#[derive(Debug, Default, Clone)]
+pub struct Symbols {
+    /// sorted by address
+    syms: Vec<Symbol>,
+    by_name: HashMap<String, usize>,
+}
+
+impl Symbols {
+    pub fn from_elf(elf: &Elf<'_>) -> Self {
+        let mut symbols = Self::default();
+        for sym in elf.syms.iter() {
+            if sym.st_type() == STT_FILE || sym.st_type() == STT_SECTION || sym.st_shndx == 0 {
+                continue;
+            }
+            let name = elf.strtab.get_at(sym.st_name).unwrap_or("");
+            if name.is_empty() || sym.st_value > 0xffff {
+                continue;
+            }
+            symbols.syms.push(Symbol {
+                name: name.to_owned(),
+                addr: sym.st_value as u16,
+                size: sym.st_size.min(0x10000 - sym.st_value) as u16,
+                func: sym.st_type() == STT_FUNC,
+            });
+        }
+        symbols.reindex();
+        symbols
+    }
+
+    /// Adds symbols of `other` whose names aren't known yet.
+    pub fn merge(&mut self, other: &Symbols) {
+        for sym in &other.syms {
+            if !self.by_name.contains_key(&sym.name) {
+                self.syms.push(sym.clone());
+            }
+        }
+        self.reindex();
+    }
+
+    fn reindex(&mut self) {
+        // stable, so the first of aliased symbols stays first
+        self.syms.sort_by_key(|sym| sym.addr);
+        self.by_name.clear();
+        for (i, sym) in self.syms.iter().enumerate() {
+            self.by_name.entry(sym.name.clone()).or_insert(i);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        Some(&self.syms[*self.by_name.get(name)?])
+    }
+
+    pub fn addr(&self, name: &str) -> Option<u16> {
+        Some(self.get(name)?.addr)
+    }
+
+    /// Symbol at or containing `addr`, with the offset of `addr` into it.
+    pub fn lookup(&self, addr: u16) -> Option<(&Symbol, u16)> {
+        let end = self.syms.partition_point(|sym| sym.addr <= addr);
+        let candidates = self.syms[..end].iter().rev();
+        // exact matches first, then the innermost symbol covering `addr`
+        if let Some(sym) = candidates.clone().take_while(|sym| sym.addr == addr).last() {
+            return Some((sym, 0));
+        }
+        candidates
+            .filter(|sym| (addr - sym.addr) < sym.size)
+            .map(|sym| (sym, addr - sym.addr))
+            .next()
+    }
+
+    /// `name` or `name+offset` for `addr`
+    pub fn symbolize(&self, addr: u16) -> Option<String> {
+        Some(match self.lookup(addr)? {
+            (sym, 0) => sym.name.clone(),
+            (sym, offset) => format!("{}+{:#x}", sym.name, offset),
+        })
+    }
+
+    /// Name of the function at `func` for reports, its address if there's
+    /// no symbol for it.
+    pub fn func_name(&self, func: Option<u16>) -> String {
+        match func {
+            Some(addr) => self
+                .symbolize(addr)
+                .unwrap_or_else(|| format!("{:04x}", addr)),
+            None => "(no symbol)".into(),
+        }
+    }
+
+    /// Function symbols, one per name.
+    pub fn functions(&self) -> impl Iterator<Item = &Symbol> + '_ {
+        self.syms
+            .iter()
+            .enumerate()
+            .filter(|(i, sym)| sym.func && self.by_name.get(&sym.name) == Some(i))
+            .map(|(_, sym)| sym)
+    }
+
+    /// Symbols starting exactly at `addr`.
+    pub fn at(&self, addr: u16) -> impl Iterator<Item = &Symbol> + '_ {
+        let start = self.syms.partition_point(|sym| sym.addr < addr);
+        self.syms[start..]
+            .iter()
+            .take_while(move |sym| sym.addr == addr)
+    }
+}
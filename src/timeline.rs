@@ -0,0 +1,181 @@
+use std::io::Write;
+
+use crate::symbols::Symbols;
+
+#[derive(Debug, Clone)]
+enum Kind {
+    /// function at given address entered
+    Enter(u16),
+    /// innermost function left
+    Exit,
+    /// point event, e.g. an interrupt request, with JSON `args`
+    Instant {
+        name: String,
+        category: &'static str,
+        args: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Event {
+    cycle: u64,
+    kind: Kind,
+}
+
+/// Function entries and exits, I/O and interrupts over time, written as
+/// Chrome trace events (for Perfetto or `chrome://tracing`).
+///
+/// Calls and returns are reported by the emulator's `CallStack`, like to
+/// the `Profiler`.
+#[derive(Debug, Default)]
+pub struct Timeline {
+    enabled: bool,
+    events: Vec<Event>,
+    /// functions entered and not left yet
+    depth: usize,
+}
+
+impl Timeline {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Starts or stops recording; when nothing was recorded yet, the timeline
+    /// starts in function `pc`.
+    pub fn set_enabled(&mut self, enabled: bool, pc: u16, cycle: u64) {
+        self.enabled = enabled;
+        if self.depth == 0 {
+            self.reset(pc, cycle);
+        }
+    }
+
+    /// Drops recorded events, the timeline continues in function `pc`.
+    pub fn reset(&mut self, pc: u16, cycle: u64) {
+        self.events.clear();
+        self.depth = 0;
+        self.call(pc, cycle);
+    }
+
+    pub fn call(&mut self, func: u16, cycle: u64) {
+        self.depth += 1;
+        self.events.push(Event {
+            cycle,
+            kind: Kind::Enter(func),
+        });
+    }
+
+    /// The innermost function returned; the one recording started in is
+    /// never left.
+    pub fn ret(&mut self, cycle: u64) {
+        if self.depth > 1 {
+            self.depth -= 1;
+            self.events.push(Event {
+                cycle,
+                kind: Kind::Exit,
+            });
+        }
+    }
+
+    pub fn instant(
+        &mut self,
+        name: String,
+        category: &'static str,
+        args: Option<String>,
+        cycle: u64,
+    ) {
+        self.events.push(Event {
+            cycle,
+            kind: Kind::Instant {
+                name,
+                category,
+                args,
+            },
+        });
+    }
+
+    /// Writes the events as Chrome trace JSON; cycles are converted to
+    /// microseconds of a CPU clocked at `clock_hz`. Functions still running
+    /// end at `now`.
+    pub fn write_json(
+        &self,
+        symbols: &Symbols,
+        clock_hz: u32,
+        now: u64,
+        out: &mut dyn Write,
+    ) -> std::io::Result<()> {
+        let ts = |cycle: u64| cycle as f64 * 1e6 / clock_hz as f64;
+        writeln!(out, "{{\"displayTimeUnit\":\"ns\",\"traceEvents\":[")?;
+        writeln!(
+            out,
+            "{{\"name\":\"process_name\",\"ph\":\"M\",\"pid\":1,\"args\":{{\"name\":\"sim6502\"}}}},"
+        )?;
+        write!(
+            out,
+            "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":1,\"args\":{{\"name\":\"6502\"}}}}"
+        )?;
+        let mut depth = 0;
+        for event in &self.events {
+            writeln!(out, ",")?;
+            match &event.kind {
+                Kind::Enter(func) => {
+                    depth += 1;
+                    let name = symbols.func_name(Some(*func));
+                    write!(
+                        out,
+                        "{{\"name\":{},\"cat\":\"call\",\"ph\":\"B\",\"ts\":{},\"pid\":1,\"tid\":1}}",
+                        json_string(&name),
+                        ts(event.cycle)
+                    )?;
+                }
+                Kind::Exit => {
+                    depth -= 1;
+                    write!(
+                        out,
+                        "{{\"ph\":\"E\",\"ts\":{},\"pid\":1,\"tid\":1}}",
+                        ts(event.cycle)
+                    )?;
+                }
+                Kind::Instant {
+                    name,
+                    category,
+                    args,
+                } => {
+                    write!(
+                        out,
+                        "{{\"name\":{},\"cat\":\"{}\",\"ph\":\"i\",\"s\":\"t\",\"ts\":{},\"pid\":1,\"tid\":1",
+                        json_string(name),
+                        category,
+                        ts(event.cycle)
+                    )?;
+                    if let Some(args) = args {
+                        write!(out, ",\"args\":{}", args)?;
+                    }
+                    write!(out, "}}")?;
+                }
+            }
+        }
+        for _ in 0..depth {
+            write!(
+                out,
+                ",\n{{\"ph\":\"E\",\"ts\":{},\"pid\":1,\"tid\":1}}",
+                ts(now)
+            )?;
+        }
+        writeln!(out, "\n]}}")
+    }
+}
+
+/// `s` as a JSON string literal
+pub(crate) fn json_string(s: &str) -> String {
+    let mut json = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            c if (c as u32) < 0x20 => json += &format!("\\u{:04x}", c as u32),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
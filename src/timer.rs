@@ -0,0 +1,95 @@
+use crate::device::Device;
+
+pub const CTRL_ENABLE: u8 = 0x01;
+pub const CTRL_PERIODIC: u8 = 0x02;
+pub const CTRL_IRQ_ENABLE: u8 = 0x04;
+/// set in status when the counter expired, write it back to acknowledge
+pub const STATUS_EXPIRED: u8 = 0x80;
+
+/// 24-bit down counter clocked by the CPU cycle count.
+///
+/// Ports, relative to the timer base:
+/// - `+0..+2` write: period (little endian). read: current count, reading
+///   `+0` latches the other two bytes
+/// - `+3` write: control (`CTRL_*`, `STATUS_EXPIRED` acknowledges).
+///   Setting `CTRL_ENABLE` (re)loads the counter with the period.
+///   read: control bits and `STATUS_EXPIRED`
+#[derive(Debug, Default)]
+pub struct Timer {
+    period: u32,
+    counter: u32,
+    counter_save: u32,
+    control: u8,
+    expired: bool,
+}
+
+impl Device for Timer {
+    fn name(&self) -> &str {
+        "timer"
+    }
+
+    fn size(&self) -> u16 {
+        4
+    }
+
+    fn read(&mut self, offset: u16) -> u8 {
+        if offset == 0 {
+            self.counter_save = self.counter;
+        }
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        match offset {
+            0 => self.counter as u8,
+            1 => (self.counter_save >> 8) as u8,
+            2 => (self.counter_save >> 16) as u8,
+            _ => self.control | if self.expired { STATUS_EXPIRED } else { 0 },
+        }
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        match offset {
+            0..=2 => {
+                let shift = offset * 8;
+                self.period = (self.period & !(0xff << shift)) | ((data as u32) << shift);
+            }
+            _ => {
+                if data & STATUS_EXPIRED != 0 {
+                    self.expired = false;
+                }
+                let control = data & (CTRL_ENABLE | CTRL_PERIODIC | CTRL_IRQ_ENABLE);
+                if control & CTRL_ENABLE != 0 && self.control & CTRL_ENABLE == 0 {
+                    self.counter = self.period;
+                }
+                self.control = control;
+            }
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        let mut cycles = cycles;
+        while self.control & CTRL_ENABLE != 0 && cycles > 0 {
+            if self.counter as u64 > cycles {
+                self.counter -= cycles as u32;
+                return;
+            }
+            cycles -= self.counter as u64;
+            self.expired = true;
+            if self.control & CTRL_PERIODIC != 0 && self.period > 0 {
+                self.counter = self.period;
+            } else {
+                self.counter = 0;
+                self.control &= !CTRL_ENABLE;
+            }
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.expired && self.control & CTRL_IRQ_ENABLE != 0
+    }
+
+    fn reset(&mut self) {
+        *self = Default::default();
+    }
+}
@@ -0,0 +1,180 @@
+use std::cell::Cell;
+use std::io::Write;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::device::Device;
+use crate::disasm::Instruction;
+use crate::emu::Registers;
+use crate::im_regs::{parse_num, ImRegMap};
+use crate::symbols::Symbols;
+
+/// Memory access made by an instruction
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Access {
+    pub addr: u16,
+    pub data: u8,
+    pub write: bool,
+}
+
+/// One executed instruction, with the registers before it ran.
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    pub cycle: u64,
+    pub regs: Registers,
+    pub insn: Instruction,
+    /// data accesses, instruction fetches left out
+    pub accesses: Vec<Access>,
+}
+
+impl TraceEntry {
+    pub fn format(&self, symbols: &Symbols, im_regs: &ImRegMap) -> String {
+        let regs = &self.regs;
+        let location = symbols.symbolize(regs.pc).unwrap_or_default();
+        let mut line = format!(
+            "{:>10}  {:04x} {:<24} {:<20} A:{:02x} X:{:02x} Y:{:02x} S:{:02x} P:{:02x}",
+            self.cycle,
+            regs.pc,
+            location,
+            self.insn.text(symbols, im_regs),
+            regs.a,
+            regs.x,
+            regs.y,
+            regs.s,
+            regs.p
+        );
+        for access in &self.accesses {
+            let kind = if access.write { 'w' } else { 'r' };
+            line += &format!(" {}:{:04x}={:02x}", kind, access.addr, access.data);
+        }
+        line
+    }
+}
+
+/// Instruction trace: which instructions to log and where to.
+#[derive(Default)]
+pub struct Trace {
+    /// shared with the trace control port
+    enabled: Rc<Cell<bool>>,
+    /// stderr if not set
+    output: Option<Box<dyn Write>>,
+    /// items of the filter as given: `START-END` or function names
+    filter_spec: Vec<String>,
+    /// PC ranges to log, everything if empty
+    filter: Vec<Range<u32>>,
+}
+
+impl Trace {
+    pub fn enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled.set(enabled);
+    }
+
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = Some(output);
+    }
+
+    /// Sets filter from comma separated list of address ranges
+    /// (`START-END`, inclusive) and function names; an empty `spec` removes
+    /// the filter. Function names are resolved with `resolve_filter`.
+    pub fn set_filter(&mut self, spec: &str) -> Result<(), String> {
+        let items: Vec<String> = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_owned)
+            .collect();
+        for item in &items {
+            if let Some((start, end)) = item.split_once('-') {
+                parse_num(start)?;
+                parse_num(end)?;
+            }
+        }
+        self.filter_spec = items;
+        self.filter.clear();
+        Ok(())
+    }
+
+    /// Turns the filter into PC ranges, looking up function names in
+    /// `symbols`. Returns names that were not found.
+    pub fn resolve_filter(&mut self, symbols: &Symbols) -> Vec<String> {
+        let mut unknown = vec![];
+        self.filter.clear();
+        for item in &self.filter_spec {
+            if let Some((start, end)) = item.split_once('-') {
+                // checked by `set_filter`
+                let (start, end) = (parse_num(start).unwrap(), parse_num(end).unwrap());
+                self.filter.push(start..end + 1);
+            } else if let Some(sym) = symbols.get(item) {
+                let start = sym.addr as u32;
+                self.filter.push(start..start + sym.size.max(1) as u32);
+            } else {
+                unknown.push(item.clone());
+            }
+        }
+        // nothing would match, rather than everything
+        if self.filter.is_empty() && !self.filter_spec.is_empty() {
+            self.filter.push(0..0);
+        }
+        unknown
+    }
+
+    /// whether the instruction at `pc` is to be logged
+    pub fn wants(&self, pc: u16) -> bool {
+        self.enabled()
+            && (self.filter.is_empty() || self.filter.iter().any(|r| r.contains(&(pc as u32))))
+    }
+
+    pub fn log(&mut self, entry: &TraceEntry, symbols: &Symbols, im_regs: &ImRegMap) {
+        let line = entry.format(symbols, im_regs);
+        let res = match &mut self.output {
+            Some(output) => writeln!(output, "{}", line),
+            None => writeln!(std::io::stderr(), "{}", line),
+        };
+        if let Err(err) = res {
+            log::warn!("writing trace failed, tracing stopped: {}", err);
+            self.set_enabled(false);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(output) = &mut self.output {
+            if let Err(err) = output.flush() {
+                log::warn!("writing trace failed: {}", err);
+            }
+        }
+    }
+
+    /// Port turning the trace on (non-zero write) and off (zero write).
+    pub fn control_port(&self) -> TraceControl {
+        TraceControl {
+            enabled: self.enabled.clone(),
+        }
+    }
+}
+
+/// Trace control port, lets programs trace only the code of interest.
+pub struct TraceControl {
+    enabled: Rc<Cell<bool>>,
+}
+
+impl Device for TraceControl {
+    fn name(&self) -> &str {
+        "trace"
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn write(&mut self, _offset: u16, data: u8) {
+        self.enabled.set(data != 0);
+    }
+
+    fn peek(&self, _offset: u16) -> u8 {
+        self.enabled.get() as u8
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+
+use gdbstub::target::ext::host_io::{HostIoErrno, HostIoOpenFlags, HostIoOpenMode, HostIoStat};
+
+/// Access mode bits of `HostIoOpenFlags` (O_RDONLY / O_WRONLY / O_RDWR)
+const O_ACCMODE: u32 = 0x3;
+
+struct InMemoryFile {
+    data: Vec<u8>,
+    mode: HostIoOpenMode,
+    /// number of directory entries pointing to this file (0 after unlink)
+    links: u32,
+}
+
+struct OpenFile {
+    path: String,
+    ino: u32,
+    flags: HostIoOpenFlags,
+    /// last position written through this fd, used to recover the full offset
+    /// from the 16-bit one passed by `pwrite`
+    pos: usize,
+    modified: bool,
+}
+
+impl OpenFile {
+    fn readable(&self) -> bool {
+        self.flags.bits() & O_ACCMODE != HostIoOpenFlags::O_WRONLY.bits()
+    }
+
+    fn writable(&self) -> bool {
+        self.flags.bits() & O_ACCMODE != HostIoOpenFlags::O_RDONLY.bits()
+    }
+}
+
+/// Very simple in-memory filesystem backing the vFile host I/O packets.
+///
+/// Files live as long as they are linked into the namespace or referenced by
+/// an open fd, just like on a POSIX system, so `platform get-file` works on
+/// anything previously uploaded with `platform put-file`.
+#[derive(Default)]
+pub struct Vfs {
+    names: HashMap<String, u32>,
+    inodes: HashMap<u32, InMemoryFile>,
+    fds: HashMap<u32, OpenFile>,
+    next_ino: u32,
+}
+
+impl Vfs {
+    pub fn open(
+        &mut self,
+        path: &str,
+        flags: HostIoOpenFlags,
+        mode: HostIoOpenMode,
+    ) -> Result<u32, HostIoErrno> {
+        let ino = match self.names.get(path).copied() {
+            Some(_) if flags.contains(HostIoOpenFlags::O_CREAT | HostIoOpenFlags::O_EXCL) => {
+                return Err(HostIoErrno::EEXIST)
+            }
+            Some(ino) => ino,
+            None if flags.contains(HostIoOpenFlags::O_CREAT) => self.link(path, vec![], mode),
+            None => return Err(HostIoErrno::ENOENT),
+        };
+
+        let file = OpenFile {
+            path: path.to_string(),
+            ino,
+            flags,
+            pos: 0,
+            modified: false,
+        };
+        if file.writable() && flags.contains(HostIoOpenFlags::O_TRUNC) {
+            self.inodes.get_mut(&ino).unwrap().data.clear();
+        }
+
+        // always hand out the lowest free descriptor, so fds never collide
+        let fd = (1..).find(|fd| !self.fds.contains_key(fd)).unwrap();
+        self.fds.insert(fd, file);
+        Ok(fd)
+    }
+
+    /// Stores `data` under `path`, replacing any previous file of that name.
+    pub fn create(&mut self, path: &str, data: Vec<u8>) {
+        if self.exists(path) {
+            self.unlink(path).unwrap();
+        }
+        self.link(
+            path,
+            data,
+            HostIoOpenMode::S_IRUSR | HostIoOpenMode::S_IWUSR,
+        );
+    }
+
+    /// Closes `fd`, returning the path and contents of the file if it was
+    /// modified through this descriptor.
+    pub fn close(&mut self, fd: u32) -> Result<Option<(String, Vec<u8>)>, HostIoErrno> {
+        let file = self.fds.remove(&fd).ok_or(HostIoErrno::EBADF)?;
+        let written = if file.modified {
+            Some((file.path, self.inodes[&file.ino].data.clone()))
+        } else {
+            None
+        };
+        self.release(file.ino);
+        Ok(written)
+    }
+
+    pub fn pread(&self, fd: u32, count: usize, offset: u64) -> Result<&[u8], HostIoErrno> {
+        let file = self.fds.get(&fd).ok_or(HostIoErrno::EBADF)?;
+        if !file.readable() {
+            return Err(HostIoErrno::EBADF);
+        }
+        let data = &self.inodes[&file.ino].data;
+        let start = (offset.min(data.len() as u64)) as usize;
+        let end = start.saturating_add(count).min(data.len());
+        Ok(&data[start..end])
+    }
+
+    pub fn pwrite(&mut self, fd: u32, offset: u16, data: &[u8]) -> Result<usize, HostIoErrno> {
+        let file = self.fds.get_mut(&fd).ok_or(HostIoErrno::EBADF)?;
+        if !file.writable() {
+            return Err(HostIoErrno::EBADF);
+        }
+        let contents = &mut self.inodes.get_mut(&file.ino).unwrap().data;
+
+        let start = if file.flags.contains(HostIoOpenFlags::O_APPEND) {
+            contents.len()
+        } else {
+            // offsets are truncated to the 16-bit target address size, so
+            // files bigger than 64K need the upper bits reconstructed: take
+            // the 64K window putting the write closest to the previous one
+            let window = file.pos & !0xffff;
+            [
+                window.checked_sub(0x10000),
+                Some(window),
+                Some(window + 0x10000),
+            ]
+            .into_iter()
+            .flatten()
+            .map(|window| window | offset as usize)
+            .min_by_key(|start| start.abs_diff(file.pos))
+            .unwrap()
+        };
+        let end = start + data.len();
+        if contents.len() < end {
+            contents.resize(end, 0);
+        }
+        contents[start..end].copy_from_slice(data);
+
+        file.pos = end;
+        file.modified = true;
+        Ok(data.len())
+    }
+
+    pub fn fstat(&self, fd: u32) -> Result<HostIoStat, HostIoErrno> {
+        let file = self.fds.get(&fd).ok_or(HostIoErrno::EBADF)?;
+        let inode = &self.inodes[&file.ino];
+        let size = inode.data.len() as u64;
+        Ok(HostIoStat {
+            st_dev: 0,
+            st_ino: file.ino,
+            st_mode: inode.mode | HostIoOpenMode::S_IFREG,
+            st_nlink: inode.links,
+            st_uid: 0,
+            st_gid: 0,
+            st_rdev: 0,
+            st_size: size,
+            st_blksize: 512,
+            st_blocks: size.div_ceil(512),
+            st_atime: 0,
+            st_mtime: 0,
+            st_ctime: 0,
+        })
+    }
+
+    pub fn unlink(&mut self, path: &str) -> Result<(), HostIoErrno> {
+        let ino = self.names.remove(path).ok_or(HostIoErrno::ENOENT)?;
+        self.inodes.get_mut(&ino).unwrap().links -= 1;
+        self.release(ino);
+        Ok(())
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.names.contains_key(path)
+    }
+
+    fn link(&mut self, path: &str, data: Vec<u8>, mode: HostIoOpenMode) -> u32 {
+        self.next_ino += 1;
+        let ino = self.next_ino;
+        self.inodes.insert(
+            ino,
+            InMemoryFile {
+                data,
+                mode,
+                links: 1,
+            },
+        );
+        self.names.insert(path.to_string(), ino);
+        ino
+    }
+
+    /// Drops the inode once it is neither linked nor open anymore.
+    fn release(&mut self, ino: u32) {
+        if self.inodes[&ino].links == 0 && !self.fds.values().any(|f| f.ino == ino) {
+            self.inodes.remove(&ino);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RDWR_CREAT: HostIoOpenFlags = HostIoOpenFlags::O_RDWR.union(HostIoOpenFlags::O_CREAT);
+
+    fn mode() -> HostIoOpenMode {
+        HostIoOpenMode::S_IRUSR | HostIoOpenMode::S_IWUSR
+    }
+
+    fn contents(vfs: &mut Vfs, path: &str) -> Vec<u8> {
+        let fd = vfs.open(path, HostIoOpenFlags::O_RDONLY, mode()).unwrap();
+        let data = vfs.pread(fd, usize::MAX, 0).unwrap().to_vec();
+        vfs.close(fd).unwrap();
+        data
+    }
+
+    #[test]
+    fn lowest_free_fd() {
+        let mut vfs = Vfs::default();
+        let a = vfs.open("a", RDWR_CREAT, mode()).unwrap();
+        let b = vfs.open("b", RDWR_CREAT, mode()).unwrap();
+        assert_eq!((a, b), (1, 2));
+        vfs.close(a).unwrap();
+        assert_eq!(vfs.open("b", HostIoOpenFlags::O_RDONLY, mode()).unwrap(), 1);
+        assert_eq!(vfs.open("a", HostIoOpenFlags::O_RDONLY, mode()).unwrap(), 3);
+        assert!(matches!(vfs.close(4), Err(HostIoErrno::EBADF)));
+    }
+
+    #[test]
+    fn open_flags() {
+        let mut vfs = Vfs::default();
+        assert!(matches!(
+            vfs.open("f", HostIoOpenFlags::O_RDWR, mode()),
+            Err(HostIoErrno::ENOENT)
+        ));
+        let fd = vfs.open("f", RDWR_CREAT, mode()).unwrap();
+        vfs.pwrite(fd, 0, b"hello").unwrap();
+        assert_eq!(
+            vfs.close(fd).unwrap(),
+            Some(("f".to_string(), b"hello".to_vec()))
+        );
+
+        let excl = RDWR_CREAT | HostIoOpenFlags::O_EXCL;
+        assert!(matches!(
+            vfs.open("f", excl, mode()),
+            Err(HostIoErrno::EEXIST)
+        ));
+
+        let append = HostIoOpenFlags::O_WRONLY | HostIoOpenFlags::O_APPEND;
+        let fd = vfs.open("f", append, mode()).unwrap();
+        vfs.pwrite(fd, 0, b" world").unwrap();
+        vfs.close(fd).unwrap();
+        assert_eq!(contents(&mut vfs, "f"), b"hello world");
+
+        // read-only opens neither truncate nor report modifications
+        let trunc = HostIoOpenFlags::O_RDONLY | HostIoOpenFlags::O_TRUNC;
+        let fd = vfs.open("f", trunc, mode()).unwrap();
+        assert!(matches!(vfs.pwrite(fd, 0, b"x"), Err(HostIoErrno::EBADF)));
+        assert_eq!(vfs.close(fd).unwrap(), None);
+        assert_eq!(contents(&mut vfs, "f"), b"hello world");
+
+        let trunc = HostIoOpenFlags::O_WRONLY | HostIoOpenFlags::O_TRUNC;
+        let fd = vfs.open("f", trunc, mode()).unwrap();
+        assert!(matches!(vfs.pread(fd, 1, 0), Err(HostIoErrno::EBADF)));
+        vfs.close(fd).unwrap();
+        assert_eq!(contents(&mut vfs, "f"), b"");
+    }
+
+    #[test]
+    fn unlink_while_open() {
+        let mut vfs = Vfs::default();
+        let fd = vfs.open("f", RDWR_CREAT, mode()).unwrap();
+        vfs.pwrite(fd, 0, b"data").unwrap();
+        vfs.unlink("f").unwrap();
+        assert!(!vfs.exists("f"));
+        assert!(matches!(vfs.unlink("f"), Err(HostIoErrno::ENOENT)));
+
+        // still readable through the open fd
+        assert_eq!(vfs.fstat(fd).unwrap().st_nlink, 0);
+        assert_eq!(vfs.pread(fd, 10, 0).unwrap(), b"data");
+
+        // a new file with the same name is a different one
+        let other = vfs.open("f", RDWR_CREAT, mode()).unwrap();
+        assert_eq!(vfs.pread(other, 10, 0).unwrap(), b"");
+        vfs.close(other).unwrap();
+
+        vfs.close(fd).unwrap();
+        assert_eq!(vfs.inodes.len(), 1);
+    }
+
+    #[test]
+    fn pwrite_beyond_64k() {
+        let mut vfs = Vfs::default();
+        let fd = vfs.open("big", RDWR_CREAT, mode()).unwrap();
+        let expected: Vec<u8> = (0..0x30000u32).map(|n| (n % 251) as u8).collect();
+        // chunks not dividing 64K, so some writes straddle the boundary
+        for (n, chunk) in expected.chunks(0x3000).enumerate() {
+            let offset = n * 0x3000;
+            assert_eq!(vfs.pwrite(fd, offset as u16, chunk).unwrap(), chunk.len());
+        }
+        vfs.close(fd).unwrap();
+        assert_eq!(contents(&mut vfs, "big"), expected);
+    }
+
+    #[test]
+    fn pwrite_rewrite_before_boundary() {
+        let mut vfs = Vfs::default();
+        let fd = vfs.open("f", RDWR_CREAT, mode()).unwrap();
+        vfs.pwrite(fd, 0xfff0, &[1; 0x20]).unwrap();
+        // the last write ended past 64K, going back a little lands in the
+        // same 64K window...
+        vfs.pwrite(fd, 0x0008, &[2; 4]).unwrap();
+        // ...and across the boundary in the previous one
+        vfs.pwrite(fd, 0xfff8, &[3; 2]).unwrap();
+        let data = vfs.pread(fd, usize::MAX, 0).unwrap();
+        assert_eq!(data.len(), 0x10010);
+        assert_eq!(&data[0xfff0..0xfff8], &[1; 8]);
+        assert_eq!(&data[0xfff8..0xfffa], &[3; 2]);
+        assert_eq!(&data[0x10008..0x1000c], &[2; 4]);
+    }
+}
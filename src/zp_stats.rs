@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use crate::im_regs::ImRegMap;
+use crate::symbols::Symbols;
+
+/// Imaginary registers the llvm-mos calling convention has callees preserve
+/// (`__rc20`-`__rc31`)
+const CALLEE_SAVED: std::ops::RangeInclusive<usize> = 20..=31;
+
+/// Reads and writes of one zero page byte
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Traffic {
+    pub reads: u64,
+    pub writes: u64,
+}
+
+impl Traffic {
+    fn total(&self) -> u64 {
+        self.reads + self.writes
+    }
+
+    fn add(&mut self, other: Traffic) {
+        self.reads += other.reads;
+        self.writes += other.writes;
+    }
+}
+
+/// CPU reads and writes per zero page byte and function (the symbol
+/// containing the instruction), reported by imaginary register.
+#[derive(Debug, Default)]
+pub struct ZeroPageStats {
+    enabled: bool,
+    /// traffic per zero page byte of every function, `None` for code
+    /// outside symbols
+    funcs: HashMap<Option<u16>, Vec<Traffic>>,
+}
+
+impl ZeroPageStats {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn reset(&mut self) {
+        self.funcs.clear();
+    }
+
+    /// Instruction at `pc` accessed zero page `addr`.
+    pub fn access(&mut self, symbols: &Symbols, pc: u16, addr: u8, write: bool) {
+        let func = symbols.lookup(pc).map(|(sym, _)| sym.addr);
+        let traffic = self
+            .funcs
+            .entry(func)
+            .or_insert_with(|| vec![Traffic::default(); 0x100]);
+        let traffic = &mut traffic[addr as usize];
+        if write {
+            traffic.writes += 1;
+        } else {
+            traffic.reads += 1;
+        }
+    }
+
+    /// traffic per zero page byte, summed over all functions
+    fn totals(&self) -> Vec<Traffic> {
+        let mut totals = vec![Traffic::default(); 0x100];
+        for traffic in self.funcs.values() {
+            for (total, traffic) in totals.iter_mut().zip(traffic) {
+                total.add(*traffic);
+            }
+        }
+        totals
+    }
+
+    /// Imaginary registers by traffic, the ones never used, callee-saved
+    /// registers written per function, other zero page bytes by traffic, and
+    /// the traffic of every function.
+    pub fn report(&self, symbols: &Symbols, im_regs: &ImRegMap) -> String {
+        let totals = self.totals();
+        let name = |addr: u8| location_name(symbols, im_regs, addr);
+
+        let mut regs: Vec<_> = im_regs
+            .iter()
+            .filter(|(_, addr)| *addr < 0x100)
+            .map(|(idx, addr)| (idx, addr as u8, totals[addr as usize]))
+            .collect();
+        regs.sort_by_key(|(idx, _, traffic)| (std::cmp::Reverse(traffic.total()), *idx));
+        let mut report = String::from("imaginary registers:\n");
+        report += &format!("{:>12} {:>12}  register\n", "reads", "writes");
+        for (_, addr, traffic) in regs.iter().filter(|(_, _, traffic)| traffic.total() > 0) {
+            report += &format!(
+                "{:>12} {:>12}  {}\n",
+                traffic.reads,
+                traffic.writes,
+                name(*addr)
+            );
+        }
+        let unused: Vec<_> = im_regs
+            .iter()
+            .filter(|(_, addr)| *addr >= 0x100 || totals[*addr as usize].total() == 0)
+            .map(|(idx, _)| format!("__rc{}", idx))
+            .collect();
+        if !unused.is_empty() {
+            report += &format!("never used: {}\n", unused.join(" "));
+        }
+
+        let funcs = self.functions();
+        let mut saved_header = false;
+        for (func, traffic) in &funcs {
+            let saved: Vec<_> = CALLEE_SAVED
+                .filter_map(|idx| im_regs.get(idx).filter(|addr| *addr < 0x100))
+                .filter(|addr| traffic[*addr as usize].writes > 0)
+                .map(|addr| name(addr as u8))
+                .collect();
+            if saved.is_empty() {
+                continue;
+            }
+            if !saved_header {
+                report += "\ncallee-saved registers written (rc20-rc31):\n";
+                saved_header = true;
+            }
+            report += &format!("  {}: {}\n", symbols.func_name(*func), saved.join(" "));
+        }
+
+        let mut other: Vec<_> = (0..=0xff)
+            .filter(|addr| im_regs.index_of(*addr as u16).is_none())
+            .map(|addr| (addr, totals[addr as usize]))
+            .filter(|(_, traffic)| traffic.total() > 0)
+            .collect();
+        if !other.is_empty() {
+            other.sort_by_key(|(addr, traffic)| (std::cmp::Reverse(traffic.total()), *addr));
+            report += "\nother zero page:\n";
+            report += &format!("{:>12} {:>12}  location\n", "reads", "writes");
+            for (addr, traffic) in other {
+                report += &format!(
+                    "{:>12} {:>12}  {}\n",
+                    traffic.reads,
+                    traffic.writes,
+                    name(addr)
+                );
+            }
+        }
+
+        for (func, traffic) in &funcs {
+            report += &format!("\n{}:\n", symbols.func_name(*func));
+            let mut used: Vec<_> = (0..=0xff)
+                .zip(traffic.iter())
+                .filter(|(_, traffic)| traffic.total() > 0)
+                .collect();
+            used.sort_by_key(|(addr, traffic)| (std::cmp::Reverse(traffic.total()), *addr));
+            for (addr, traffic) in used {
+                report += &format!(
+                    "{:>12} {:>12}  {}\n",
+                    traffic.reads,
+                    traffic.writes,
+                    name(addr)
+                );
+            }
+        }
+        report
+    }
+
+    /// functions by zero page traffic
+    fn functions(&self) -> Vec<(Option<u16>, &[Traffic])> {
+        let mut funcs: Vec<_> = self
+            .funcs
+            .iter()
+            .map(|(func, traffic)| (*func, traffic.as_slice()))
+            .collect();
+        funcs.sort_by_key(|(func, traffic)| {
+            let total: u64 = traffic.iter().map(Traffic::total).sum();
+            (std::cmp::Reverse(total), *func)
+        });
+        funcs
+    }
+}
+
+/// `__rcN`, symbol or `$xx` for zero page `addr`
+fn location_name(symbols: &Symbols, im_regs: &ImRegMap, addr: u8) -> String {
+    if let Some(idx) = im_regs.index_of(addr as u16) {
+        return format!("__rc{}", idx);
+    }
+    symbols
+        .symbolize(addr as u16)
+        .unwrap_or_else(|| format!("${:02x}", addr))
+}
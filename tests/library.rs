@@ -0,0 +1,88 @@
+use sim6502::{Emu, Machine, MemRegion, Registers, StopReason};
+
+/// `LDA #'A'`, `STA $FFF9`, `LDX $0300`, `STX $FFF8`: prints `A` and exits
+/// with the status stored at `$0300`.
+const PROGRAM: &[u8] = &[
+    0xa9, 0x41, 0x8d, 0xf9, 0xff, 0xae, 0x00, 0x03, 0x8e, 0xf8, 0xff,
+];
+
+fn load() -> Emu {
+    let mut emu = Emu::new(Machine::default()).unwrap();
+    emu.load_image(0x0200, PROGRAM, 0x0200);
+    emu
+}
+
+#[test]
+fn runs_image_to_exit() {
+    let mut emu = load();
+    emu.write_memory(0x0300, &[7]);
+    assert_eq!(emu.run_for(1_000), StopReason::Exited(7));
+    assert_eq!(emu.exit_code(), Some(7));
+    assert_eq!(emu.output(), b"A");
+    let regs = emu.registers();
+    assert_eq!((regs.a, regs.x), (b'A', 7));
+}
+
+#[test]
+fn stops_at_cycle_limit() {
+    let mut emu = load();
+    assert_eq!(emu.run_for(3), StopReason::CycleLimit);
+    assert_eq!(emu.exit_code(), None);
+    assert!(emu.output().is_empty());
+}
+
+#[test]
+fn registers_and_memory() {
+    let mut emu = load();
+    let mut code = [0; 2];
+    emu.read_memory(0x0200, &mut code);
+    assert_eq!(code, [0xa9, 0x41]);
+
+    // skip the console write
+    let regs = Registers {
+        pc: 0x0205,
+        ..emu.registers()
+    };
+    emu.set_registers(&regs);
+    assert_eq!(emu.registers(), regs);
+    assert_eq!(emu.run_for(1_000), StopReason::Exited(0));
+    assert!(emu.output().is_empty());
+}
+
+#[test]
+fn rejects_machine_beyond_address_space() {
+    let machine = Machine {
+        ram: vec![MemRegion {
+            start: 0xff00,
+            size: 0x200,
+        }],
+        ..Machine::default()
+    };
+    assert!(Emu::new(machine).is_err());
+}
+
+/// `JSR $0210`, `LDX #0`, `STX $FFF8`; `RTS` at `$0210`
+const CALL: &[u8] = &[0x20, 0x10, 0x02, 0xa2, 0x00, 0x8e, 0xf8, 0xff];
+
+#[test]
+fn profile_and_timeline_follow_calls() {
+    let mut emu = Emu::new(Machine::default()).unwrap();
+    emu.load_image(0x0200, CALL, 0x0200);
+    emu.write_memory(0x0210, &[0x60]);
+    emu.set_profiling(true);
+    emu.set_timeline(true);
+    assert_eq!(emu.run_for(1_000), StopReason::Exited(0));
+
+    let report = emu.profile_report();
+    let callee = report.lines().find(|line| line.ends_with("0210")).unwrap();
+    let columns: Vec<_> = callee.split_whitespace().collect();
+    // inclusive, %, exclusive, %, calls: the RTS
+    assert_eq!((columns[0], columns[2], columns[4]), ("6", "6", "1"));
+
+    let mut timeline = vec![];
+    emu.write_timeline(&mut timeline).unwrap();
+    let timeline = String::from_utf8(timeline).unwrap();
+    assert!(timeline.contains("\"name\":\"0210\",\"cat\":\"call\",\"ph\":\"B\""));
+    assert_eq!(timeline.matches("\"ph\":\"B\"").count(), 2);
+    assert_eq!(timeline.matches("\"ph\":\"E\"").count(), 2);
+}